@@ -2,6 +2,7 @@ use iced::alignment::Horizontal::{Left, Center as xCenter};
 use iced::alignment::Vertical::Bottom;
 use iced::widget::{
     button,
+    checkbox,
     column,
     container,
     row,
@@ -17,8 +18,17 @@ use iced::Length::Fill;
 use iced::Theme;
 use iced::theme::Palette;
 use iced::Color;
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
 use crate::config;
-use crate::config::{GAMEMON_CONFIG_FILE, ensure_paths_exist};
+use crate::bundle;
+use crate::migration;
+use crate::validate::{self, Diagnostic, Severity};
+use crate::config::{CommandSpec, FailurePolicy, GAMEMON_CONFIG_FILE, LogLevel, ensure_paths_exist};
+use crate::executor::{self, CommandReport};
+use crate::service::CommandContext;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
 
 fn get_system_palette() -> Palette {
     let is_dark = detect_gtk_dark_mode();
@@ -61,18 +71,7 @@ fn detect_gtk_dark_mode() -> bool {
         })
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum ThemeType {
-    System,
-    Dark,
-    Light,
-}
-
-impl Default for ThemeType {
-    fn default() -> Self {
-        ThemeType::System
-    }
-}
+pub use crate::config::ThemeType;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ViewState {
@@ -96,7 +95,14 @@ pub struct Gui {
     end_commands_field: text_editor::Content,
     entry_changed: bool,
     view_state: ViewState,
-    selected_theme: ThemeType,
+    settings: config::Settings,
+    poll_interval_field: String,
+    /// Problems in the saved config, shown next to the fields they concern.
+    diagnostics: Vec<Diagnostic>,
+    /// Results of the last Run button, filled in by a background thread.
+    test_reports: Arc<Mutex<Vec<CommandReport>>>,
+    /// The config as it last loaded, used while the file doesn't parse.
+    config: config::Config,
 }
 
 impl Default for Gui {
@@ -104,20 +110,31 @@ impl Default for Gui {
         if let Err(e) = ensure_paths_exist() {
             log::error!("Error ensuring paths exist: {}", e);
         }
-        let config = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap();
+        if let Err(e) = migration::migrate_file(GAMEMON_CONFIG_FILE.as_path()) {
+            log::error!("Failed to migrate config: {}", e);
+        }
+        let config = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap_or_else(|e| {
+            // The parse error is among the diagnostics below
+            log::error!("Failed to load config: {}", e);
+            config::Config::default()
+        });
         let game_names: Vec<String> = config.entries.iter().map(|e| e.game_name.clone()).collect();
 
         Self {
             game_names,
             selected_game_name: None,
             selected_game_entry: None,
-            game_name_field: "Enter game name...".to_string(),
-            game_executable_field: "Enter game executable...".to_string(),
-            start_commands_field: text_editor::Content::with_text("Enter start commands..."),
-            end_commands_field: text_editor::Content::with_text("Enter end commands..."),
+            game_name_field: String::new(),
+            game_executable_field: String::new(),
+            start_commands_field: text_editor::Content::new(),
+            end_commands_field: text_editor::Content::new(),
             entry_changed: false,
             view_state: ViewState::Profiles,
-            selected_theme: ThemeType::System,
+            poll_interval_field: config.service.poll_interval.to_string(),
+            settings: config.settings.clone(),
+            diagnostics: validate::validate_file(GAMEMON_CONFIG_FILE.as_path()).unwrap_or_default(),
+            test_reports: Arc::default(),
+            config,
         }
     }
 }
@@ -137,11 +154,19 @@ pub enum Message {
     OpenSettings,
     CloseSettings,
     ThemeSelected(ThemeType),
+    LogLevelSelected(LogLevel),
+    PollIntervalChanged(String),
+    NotificationsToggled(bool),
+    SessionStartNotificationsToggled(bool),
+    SessionEndNotificationsToggled(bool),
+    AutoUpdateToggled(bool),
+    ExportEntry,
+    ImportBundle,
 }
 
 impl Gui {
     pub fn theme(&self) -> Theme {
-        match self.selected_theme {
+        match self.settings.theme {
             ThemeType::Dark => iced::Theme::Dark,
             ThemeType::Light => iced::Theme::Light,
             ThemeType::System => {
@@ -157,14 +182,14 @@ impl Gui {
                 if self.entry_changed {
                     self.save_current_entry();
                 }
-                let config = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap();
+                let config = self.load_config().unwrap_or_else(|| self.config.clone());
                 if let Some(selected_entry) = config.entries.iter().find(|entry| entry.game_name == game_name) {
                     self.selected_game_name = Some(game_name.clone());
                     self.selected_game_entry = Some(selected_entry.clone());
                     self.game_name_field = selected_entry.game_name.clone();
                     self.game_executable_field = selected_entry.executable.clone();
-                    self.start_commands_field = text_editor::Content::with_text(&selected_entry.start_commands.join("\n"));
-                    self.end_commands_field = text_editor::Content::with_text(&selected_entry.end_commands.join("\n"));
+                    self.start_commands_field = text_editor::Content::with_text(&editor_text(&selected_entry.start_commands));
+                    self.end_commands_field = text_editor::Content::with_text(&editor_text(&selected_entry.end_commands));
                     self.entry_changed = false;
                 }
             }
@@ -173,45 +198,20 @@ impl Gui {
                 self.entry_changed = true;
             }
             Message::TestStartCommands => {
-                for cmd in self.start_commands_field.text().lines() {
-                    if !cmd.trim().is_empty() {
-                        if let Err(e) = std::process::Command::new("sh")
-                            .arg("-c")
-                            .arg(cmd)
-                            .spawn()
-                        {
-                            log::error!("Failed to run start command '{}': {}", cmd, e);
-                        }
-                    }
-                }
+                self.run_test_commands(editor_lines(&self.start_commands_field));
             }
             Message::EndCommandsChanged(action) => {
                 self.end_commands_field.perform(action);
                 self.entry_changed = true;
             }
             Message::TestEndCommands => {
-                for cmd in self.end_commands_field.text().lines() {
-                    if !cmd.trim().is_empty() {
-                        if let Err(e) = std::process::Command::new("sh")
-                            .arg("-c")
-                            .arg(cmd)
-                            .spawn()
-                        {
-                            log::error!("Failed to run end command '{}': {}", cmd, e);
-                        }
-                    }
-                }
+                self.run_test_commands(editor_lines(&self.end_commands_field));
             }
             Message::NewEntry => {
                 if self.entry_changed {
                     self.save_current_entry();
                 }
-                self.selected_game_name = None;
-                self.selected_game_entry = None;
-                self.game_name_field = "Enter game name...".to_string();
-                self.game_executable_field = "Enter game executable...".to_string();
-                self.start_commands_field = text_editor::Content::with_text("Enter start commands...");
-                self.end_commands_field = text_editor::Content::with_text("Enter end commands...");
+                self.clear_entry_fields();
                 self.entry_changed = false;
                 self.view_state = ViewState::Profiles;
             }
@@ -219,17 +219,23 @@ impl Gui {
                 self.save_current_entry();
             }
             Message::RemoveEntry => {
-                let mut config = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap();
+                let Some(mut config) = self.load_config() else {
+                    return;
+                };
                 if let Some(index) = config.entries.iter().position(|entry| entry.game_name == self.game_name_field) {
-                    config.entries.remove(index);
-                    config::Config::save_to_file(&config, &GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap();
+                    let removed = config.entries.remove(index);
+                    // A profile from profiles.d is its own file
+                    if let Some(source) = &removed.source {
+                        if let Err(e) = std::fs::remove_file(source) {
+                            log::error!("Failed to remove profile {}: {}", source.display(), e);
+                        }
+                    } else if let Err(e) = config::Config::save_to_file(&config, &GAMEMON_CONFIG_FILE.to_string_lossy()) {
+                        log::error!("Failed to save config: {}", e);
+                        return;
+                    }
                     self.game_names = config.entries.iter().map(|e| e.game_name.clone()).collect();
-                    self.selected_game_name = None;
-                    self.selected_game_entry = None;
-                    self.game_name_field = "Enter game name...".to_string();
-                    self.game_executable_field = "Enter game executable...".to_string();
-                    self.start_commands_field = text_editor::Content::with_text("Enter start commands...");
-                    self.end_commands_field = text_editor::Content::with_text("Enter end commands...");
+                    self.clear_entry_fields();
+                    self.refresh_diagnostics();
                 }
             }
             Message::GameNameChanged(content) => {
@@ -250,7 +256,45 @@ impl Gui {
                 self.view_state = ViewState::Profiles;
             }
             Message::ThemeSelected(theme) => {
-                self.selected_theme = theme;
+                self.settings.theme = theme;
+                self.save_settings();
+            }
+            Message::LogLevelSelected(level) => {
+                self.settings.log_level = level;
+                log::set_max_level(level.filter());
+                self.save_settings();
+            }
+            Message::PollIntervalChanged(content) => {
+                self.poll_interval_field = content;
+                self.save_settings();
+            }
+            Message::NotificationsToggled(enabled) => {
+                self.settings.notifications.enabled = enabled;
+                self.save_settings();
+            }
+            Message::SessionStartNotificationsToggled(enabled) => {
+                self.settings.notifications.session_start = enabled;
+                self.save_settings();
+            }
+            Message::SessionEndNotificationsToggled(enabled) => {
+                self.settings.notifications.session_end = enabled;
+                self.save_settings();
+            }
+            Message::AutoUpdateToggled(enabled) => {
+                self.settings.updates.auto_check = enabled;
+                self.save_settings();
+            }
+            Message::ExportEntry => {
+                if self.entry_changed {
+                    self.save_current_entry();
+                }
+                self.export_selected_entry();
+            }
+            Message::ImportBundle => {
+                if self.entry_changed {
+                    self.save_current_entry();
+                }
+                self.import_bundle();
             }
         }
     }
@@ -273,7 +317,10 @@ impl Gui {
         let profile_list: Vec<iced::Element<'_, Message>> = self.game_names.iter()
             .map(|name| {
                 let is_selected = self.selected_game_name.as_deref() == Some(name);
-                let label = if is_selected { format!("▶ {}", name) } else { name.clone() };
+                let mut label = if is_selected { format!("▶ {}", name) } else { name.clone() };
+                if self.diagnostics.iter().any(|d| d.game_name.as_ref() == Some(name)) {
+                    label.push_str(" ⚠");
+                }
                 button(text(label).size(14))
                     .width(Fill)
                     .padding(8)
@@ -317,6 +364,7 @@ impl Gui {
     fn profiles_view(&self) -> iced::widget::container::Container<'_, Message> {
         container(
             column![
+                self.file_diagnostics(),
                 row![
                     column![
                         vertical_space().height(10),
@@ -339,6 +387,8 @@ impl Gui {
                         .on_input(Message::GameExectuableChanged),
                     horizontal_space().width(10),
                 ],
+                self.field_diagnostics(&["game_name", "executable", "match", "extends"]),
+                self.template_note(),
                 vertical_space().height(10),
                 text("Start Commands:").align_x(Left),
                 row![
@@ -353,6 +403,7 @@ impl Gui {
                         .height(30)
                         .width(50),
                 ],
+                self.field_diagnostics(&["start_commands"]),
                 vertical_space().height(10),
                 text("End Commands:").align_x(Left),
                 row![
@@ -367,6 +418,8 @@ impl Gui {
                         .height(30)
                         .width(50),
                 ],
+                self.field_diagnostics(&["end_commands"]),
+                self.test_report_view(),
                 vertical_space().height(Fill),
                 row![
                     button("New Entry")
@@ -380,6 +433,14 @@ impl Gui {
                     button("Save Entry")
                         .on_press(Message::SaveEntry)
                         .padding(10),
+                    horizontal_space().width(Fill),
+                    button("Import...")
+                        .on_press(Message::ImportBundle)
+                        .padding(10),
+                    horizontal_space().width(10),
+                    button("Export...")
+                        .on_press_maybe(self.selected_game_name.as_ref().map(|_| Message::ExportEntry))
+                        .padding(10),
                 ]
             ]
             .padding(20)
@@ -397,47 +458,382 @@ impl Gui {
                 .on_press(Message::ThemeSelected(theme))
         };
 
+        let log_level_buttons = LogLevel::ALL.iter().fold(row![], |row, level| {
+            let name = format!("{:?}", level);
+            let label = if *level == self.settings.log_level { format!("● {}", name) } else { format!("  {}", name) };
+            row.push(
+                button(text(label).size(14))
+                    .padding(10)
+                    .on_press(Message::LogLevelSelected(*level)),
+            )
+            .push(horizontal_space().width(5))
+        });
+
+        let notifications = &self.settings.notifications;
+
         container(
-            column![
+            scrollable(column![
                 row![
                     button("← Back").padding(8).on_press(Message::CloseSettings),
                     horizontal_space().width(10),
                     text("Settings").size(20).align_y(Bottom),
                 ],
+                // Settings can't be saved while the file doesn't parse
+                self.file_diagnostics(),
                 vertical_space().height(20),
                 text("Theme").size(16),
                 vertical_space().height(5),
-                theme_button("System Default", ThemeType::System, self.selected_theme == ThemeType::System),
+                theme_button("System Default", ThemeType::System, self.settings.theme == ThemeType::System),
                 vertical_space().height(5),
-                theme_button("Dark", ThemeType::Dark, self.selected_theme == ThemeType::Dark),
+                theme_button("Dark", ThemeType::Dark, self.settings.theme == ThemeType::Dark),
+                vertical_space().height(5),
+                theme_button("Light", ThemeType::Light, self.settings.theme == ThemeType::Light),
+                vertical_space().height(20),
+                text("Monitoring").size(16),
                 vertical_space().height(5),
-                theme_button("Light", ThemeType::Light, self.selected_theme == ThemeType::Light),
+                row![
+                    column![
+                        vertical_space().height(10),
+                        text("Poll interval (seconds):").size(14).align_y(Bottom),
+                    ],
+                    horizontal_space().width(10),
+                    text_input("5", &self.poll_interval_field)
+                        .padding(10)
+                        .size(14)
+                        .width(80)
+                        .on_input(Message::PollIntervalChanged),
+                ],
+                vertical_space().height(20),
+                text("Notifications").size(16),
+                vertical_space().height(5),
+                checkbox("Show notifications", notifications.enabled)
+                    .on_toggle(Message::NotificationsToggled),
+                vertical_space().height(5),
+                checkbox("When a game starts", notifications.session_start)
+                    .on_toggle(Message::SessionStartNotificationsToggled),
+                vertical_space().height(5),
+                checkbox("When a game ends", notifications.session_end)
+                    .on_toggle(Message::SessionEndNotificationsToggled),
+                vertical_space().height(20),
+                text("Updates").size(16),
+                vertical_space().height(5),
+                checkbox("Check for updates automatically", self.settings.updates.auto_check)
+                    .on_toggle(Message::AutoUpdateToggled),
+                vertical_space().height(20),
+                text("Log Level").size(16),
+                vertical_space().height(5),
+                log_level_buttons,
             ]
             .padding(20)
-            .align_x(Left)
+            .align_x(Left))
         )
         .padding(10)
         .width(Fill)
     }
 
+    /// Writes the settings view back to the `[settings]` and `[service]` tables.
+    /// An unparsable poll interval is left out until it is corrected.
+    fn save_settings(&mut self) {
+        let Some(mut config) = self.load_config() else {
+            return;
+        };
+        config.settings = self.settings.clone();
+        if let Ok(interval) = self.poll_interval_field.trim().parse::<u64>() {
+            if interval > 0 {
+                config.service.poll_interval = interval;
+            }
+        }
+        if let Err(e) = config::Config::save_to_file(&config, &GAMEMON_CONFIG_FILE.to_string_lossy()) {
+            log::error!("Failed to save settings: {}", e);
+        }
+    }
+
     fn save_current_entry(&mut self) {
-        let mut config = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap();
+        // The edits stay in the fields until the file can be saved again
+        let Some(mut config) = self.load_config() else {
+            return;
+        };
         if let Some(index) = config.entries.iter().position(|entry| entry.game_name == self.game_name_field) {
             config.entries[index].game_name = self.game_name_field.clone();
             config.entries[index].executable = self.game_executable_field.clone();
-            config.entries[index].start_commands = self.start_commands_field.text().split("\n").map(|s| s.to_string()).collect();
-            config.entries[index].end_commands = self.end_commands_field.text().split("\n").map(|s| s.to_string()).collect();
+            config.entries[index].start_commands = editor_lines(&self.start_commands_field);
+            config.entries[index].end_commands = editor_lines(&self.end_commands_field);
+
+            // Profiles from profiles.d are saved back to their own file
+            if config.entries[index].source.is_some() {
+                if let Err(e) = config.entries[index].save_to_source() {
+                    log::error!("Failed to save profile: {}", e);
+                }
+                self.entry_changed = false;
+                self.refresh_diagnostics();
+                return;
+            }
         } else {
             let new_entry = config::Entry {
                 game_name: self.game_name_field.clone(),
                 executable: self.game_executable_field.clone(),
-                start_commands: self.start_commands_field.text().split("\n").map(|s| s.to_string()).collect(),
-                end_commands: self.end_commands_field.text().split("\n").map(|s| s.to_string()).collect(),
+                start_commands: editor_lines(&self.start_commands_field),
+                end_commands: editor_lines(&self.end_commands_field),
+                ..Default::default()
             };
             config.entries.push(new_entry);
         }
-        config::Config::save_to_file(&config, &GAMEMON_CONFIG_FILE.to_string_lossy()).unwrap();
+        if let Err(e) = config::Config::save_to_file(&config, &GAMEMON_CONFIG_FILE.to_string_lossy()) {
+            log::error!("Failed to save config: {}", e);
+            return;
+        }
         self.game_names = config.entries.iter().map(|e| e.game_name.clone()).collect();
         self.entry_changed = false;
+        self.refresh_diagnostics();
     }
-}
\ No newline at end of file
+
+    /// Saves the selected entry as a bundle file chosen by the user.
+    fn export_selected_entry(&self) {
+        let Some(game_name) = self.selected_game_name.clone() else {
+            return;
+        };
+        let Some(path) = FileDialog::new()
+            .set_title("Export Profile")
+            .add_filter("TOML", &["toml"])
+            .add_filter("JSON", &["json"])
+            .set_file_name(format!("{}.toml", game_name))
+            .save_file()
+        else {
+            return;
+        };
+
+        let meta = bundle::BundleMeta {
+            author: std::env::var("USER").unwrap_or_default(),
+            target_os: vec![std::env::consts::OS.to_string()],
+            ..Default::default()
+        };
+        let result = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy())
+            .map_err(|e| e.to_string())
+            .and_then(|config| bundle::export(&config, &[game_name], meta, &path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            log::error!("Failed to export profile: {}", e);
+            MessageDialog::new()
+                .set_level(MessageLevel::Error)
+                .set_title("Export Failed")
+                .set_description(e)
+                .set_buttons(MessageButtons::Ok)
+                .show();
+        }
+    }
+
+    /// Adds the profiles from a bundle file chosen by the user, asking
+    /// whether to rename or merge profiles whose name is taken.
+    fn import_bundle(&mut self) {
+        let Some(path) = FileDialog::new()
+            .set_title("Import Profiles")
+            .add_filter("Profile bundle", &["toml", "json"])
+            .pick_file()
+        else {
+            return;
+        };
+
+        let config_path = GAMEMON_CONFIG_FILE.to_string_lossy();
+        let bundle = match bundle::Bundle::load(&path) {
+            Ok(bundle) => bundle,
+            Err(e) => {
+                log::error!("Failed to read bundle {}: {}", path.display(), e);
+                MessageDialog::new()
+                    .set_level(MessageLevel::Error)
+                    .set_title("Import Failed")
+                    .set_description(e.to_string())
+                    .set_buttons(MessageButtons::Ok)
+                    .show();
+                return;
+            }
+        };
+
+        let mut notes = Vec::new();
+        if !bundle.meta.author.is_empty() {
+            notes.push(format!("By {}", bundle.meta.author));
+        }
+        if !bundle.meta.description.is_empty() {
+            notes.push(bundle.meta.description.clone());
+        }
+        notes.extend(bundle.warnings().into_iter().map(|w| format!("⚠ {}", w)));
+
+        let collisions = config::Config::load_from_file(&config_path)
+            .map(|config| bundle.collisions(&config))
+            .unwrap_or_default();
+        let resolution = if collisions.is_empty() {
+            bundle::Resolution::Skip
+        } else {
+            notes.push(format!("Already in your profiles: {}", collisions.join(", ")));
+            let choice = MessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("Import Profiles")
+                .set_description(notes.join("\n\n"))
+                .set_buttons(MessageButtons::YesNoCancelCustom(
+                    "Rename".to_string(),
+                    "Merge".to_string(),
+                    "Cancel".to_string(),
+                ))
+                .show();
+            match choice {
+                MessageDialogResult::Custom(label) if label == "Rename" => bundle::Resolution::Rename,
+                MessageDialogResult::Custom(label) if label == "Merge" => bundle::Resolution::Merge,
+                _ => return,
+            }
+        };
+
+        match bundle::import_into_file(bundle, &config_path, resolution) {
+            Ok(summary) => {
+                log::info!("Imported {:?} from {}", summary, path.display());
+                if let Ok(config) = config::Config::load_from_file(&config_path) {
+                    self.game_names = config.entries.iter().map(|e| e.game_name.clone()).collect();
+                }
+                self.refresh_diagnostics();
+            }
+            Err(e) => log::error!("Failed to import {}: {}", path.display(), e),
+        }
+    }
+
+    fn clear_entry_fields(&mut self) {
+        self.selected_game_name = None;
+        self.selected_game_entry = None;
+        self.game_name_field.clear();
+        self.game_executable_field.clear();
+        self.start_commands_field = text_editor::Content::new();
+        self.end_commands_field = text_editor::Content::new();
+    }
+
+    /// Loads the config file and keeps it as the last good one. If it doesn't
+    /// parse, `None` is returned so nothing is saved over it, and the parse
+    /// error is shown with the file's diagnostics.
+    fn load_config(&mut self) -> Option<config::Config> {
+        match config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
+            Ok(config) => {
+                self.config = config.clone();
+                Some(config)
+            }
+            Err(e) => {
+                log::error!("Failed to load config: {}", e);
+                self.refresh_diagnostics();
+                None
+            }
+        }
+    }
+
+    fn refresh_diagnostics(&mut self) {
+        match validate::validate_file(GAMEMON_CONFIG_FILE.as_path()) {
+            Ok(diagnostics) => self.diagnostics = diagnostics,
+            Err(e) => log::error!("Failed to validate config: {}", e),
+        }
+    }
+
+    /// Names the template the selected entry extends, since its commands
+    /// only show the entry's own part.
+    fn template_note(&self) -> iced::widget::Column<'_, Message> {
+        match self.selected_game_entry.as_ref().and_then(|entry| entry.extends.as_ref()) {
+            Some(template) => column![
+                text(format!("Extends template '{}': its commands run as well.", template))
+                    .size(12)
+                    .color([0.5, 0.5, 0.5])
+            ],
+            None => column![],
+        }
+    }
+
+    /// Runs `commands` on a background thread the way the service would for
+    /// the selected entry, keeping the reports to show under the editors.
+    fn run_test_commands(&self, commands: Vec<CommandSpec>) {
+        let config = config::Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy())
+            .unwrap_or_else(|_| self.config.clone());
+        let entry = self.selected_game_entry.clone().unwrap_or_default();
+        let entry = config.resolve_entry(&entry).unwrap_or(entry);
+        let timeout = config.command_timeout(Some(&entry));
+
+        let reports = self.test_reports.clone();
+        if let Ok(mut reports) = reports.lock() {
+            reports.clear();
+        }
+        thread::spawn(move || {
+            // No session is running, so PIDs expand to nothing
+            let context = CommandContext::for_entry(&entry, SystemTime::now());
+            let commands: Vec<CommandSpec> = commands.iter().map(|spec| context.expand_command(spec)).collect();
+            let stop_on_failure = entry.on_failure != FailurePolicy::Continue;
+            let results = executor::run_all(&commands, &context.env(), timeout, entry.mode, stop_on_failure);
+            if let Ok(mut reports) = reports.lock() {
+                *reports = results;
+            }
+        });
+    }
+
+    /// One line per command of the last test run, with the start of its
+    /// error output if it failed.
+    fn test_report_view(&self) -> iced::widget::Column<'_, Message> {
+        let Ok(reports) = self.test_reports.lock() else {
+            return column![];
+        };
+
+        reports.iter().fold(column![], |col, report| {
+            let color = if report.succeeded() { [0.2, 0.6, 0.3] } else { [0.85, 0.2, 0.2] };
+            let col = col.push(text(report.to_string()).size(12).color(color));
+            match report.stderr.lines().next() {
+                Some(line) if !report.succeeded() => col.push(text(format!("    {}", line)).size(12)),
+                _ => col,
+            }
+        })
+    }
+
+    /// Problems with a whole file rather than one entry, such as a parse error.
+    fn file_diagnostics(&self) -> iced::widget::Column<'_, Message> {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.game_name.is_none())
+            .fold(column![], |col, d| col.push(diagnostic_text(d, true)))
+    }
+
+    /// Problems with the selected entry in any of `fields`, including their
+    /// sub-fields such as `start_commands[2]` or `match.cmdline`.
+    fn field_diagnostics(&self, fields: &[&str]) -> iced::widget::Column<'_, Message> {
+        let Some(selected) = self.selected_game_name.as_ref() else {
+            return column![];
+        };
+
+        self.diagnostics
+            .iter()
+            .filter(|d| d.game_name.as_ref() == Some(selected))
+            .filter(|d| d.field.split(['[', '.']).next().is_some_and(|f| fields.contains(&f)))
+            .fold(column![], |col, d| col.push(diagnostic_text(d, false)))
+    }
+}
+
+/// Editor text as one command per line, without the blank lines the editor leaves behind.
+fn editor_lines(content: &text_editor::Content) -> Vec<CommandSpec> {
+    content
+        .text()
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(CommandSpec::from_line)
+        .collect()
+}
+
+fn editor_text(commands: &[CommandSpec]) -> String {
+    commands.iter().map(CommandSpec::to_line).collect::<Vec<_>>().join("\n")
+}
+
+fn diagnostic_text(diagnostic: &Diagnostic, with_field: bool) -> iced::widget::Text<'_> {
+    let (icon, color) = match diagnostic.severity {
+        Severity::Error => ("✖", [0.85, 0.2, 0.2]),
+        Severity::Warning => ("⚠", [0.85, 0.6, 0.1]),
+    };
+
+    let mut label = String::from(icon);
+    if let Some(file) = diagnostic.file.as_ref().and_then(|f| f.file_name()) {
+        label.push_str(&format!(" {}", file.to_string_lossy()));
+    }
+    if let Some((line, _)) = diagnostic.location {
+        label.push_str(&format!(" line {}:", line));
+    }
+    if (with_field || diagnostic.field.contains(['[', '.'])) && !diagnostic.field.is_empty() {
+        label.push_str(&format!(" {}:", diagnostic.field));
+    }
+    label.push_str(&format!(" {}", diagnostic.message));
+
+    text(label).size(12).color(color)
+}
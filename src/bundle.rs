@@ -0,0 +1,633 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::config::{Config, Entry, Resource, Template, CURRENT_VERSION};
+
+/// Format version written by this build. Newer bundles are refused.
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Describes where a bundle comes from and what its commands expect.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct BundleMeta {
+    pub author: String,
+    pub description: String,
+    /// Operating systems the profiles were made for, as in `std::env::consts::OS`. Empty means any.
+    pub target_os: Vec<String>,
+    /// Programs the commands need on `PATH`, e.g. `gamemoded` or `pactl`.
+    pub requires: Vec<String>,
+    /// GameMon version that wrote the bundle.
+    pub gamemon_version: String,
+}
+
+/// One or more entries in a self-contained file, for sharing profiles.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Bundle {
+    #[serde(default)]
+    pub bundle_version: u32,
+    #[serde(default)]
+    pub meta: BundleMeta,
+    /// Definitions of the `[resources]` the entries hold.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<String, Resource>,
+    /// The `[templates]` the entries extend, with their own parents.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub templates: BTreeMap<String, Template>,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BundleFormat {
+    Toml,
+    Json,
+}
+
+impl BundleFormat {
+    /// JSON for `.json` files, TOML for anything else.
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => BundleFormat::Json,
+            _ => BundleFormat::Toml,
+        }
+    }
+}
+
+/// What to do with a bundle entry whose game name is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Add it under a free name, e.g. `Elden Ring (imported)`.
+    Rename,
+    /// Fold it into the existing entry, see `merge_entry`.
+    Merge,
+    /// Leave the existing entry alone.
+    Skip,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    pub added: Vec<String>,
+    pub merged: Vec<String>,
+    pub skipped: Vec<String>,
+    /// Resource definitions the config didn't have yet.
+    pub resources: Vec<String>,
+    /// Templates added, under the name they were added as.
+    pub templates: Vec<String>,
+}
+
+impl Bundle {
+    pub fn new(entries: Vec<Entry>, meta: BundleMeta) -> Self {
+        let entries = entries
+            .into_iter()
+            .map(|entry| Entry { source: None, ..entry })
+            .collect();
+        Self {
+            bundle_version: BUNDLE_VERSION,
+            meta: BundleMeta { gamemon_version: CURRENT_VERSION.to_string(), ..meta },
+            resources: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            entries,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error + Send>> {
+        let data = fs::read_to_string(path).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+        let bundle: Bundle = match BundleFormat::for_path(path) {
+            BundleFormat::Json => serde_json::from_str(&data).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?,
+            BundleFormat::Toml => toml::from_str(&data).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?,
+        };
+
+        if bundle.bundle_version > BUNDLE_VERSION {
+            return Err(Box::new(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "bundle version {} is newer than this GameMon supports (version {})",
+                    bundle.bundle_version, BUNDLE_VERSION
+                ),
+            )));
+        }
+
+        Ok(bundle)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let data = match BundleFormat::for_path(path) {
+            BundleFormat::Json => serde_json::to_string_pretty(self)?,
+            BundleFormat::Toml => toml::to_string_pretty(self)?,
+        };
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Reasons the bundle may not work here: another target OS, or required
+    /// programs missing from `PATH`.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        let os = env::consts::OS;
+        if !self.meta.target_os.is_empty() && !self.meta.target_os.iter().any(|t| t.eq_ignore_ascii_case(os)) {
+            warnings.push(format!("made for {}, this is {}", self.meta.target_os.join(", "), os));
+        }
+
+        for tool in self.meta.requires.iter().filter(|tool| !on_path(tool)) {
+            warnings.push(format!("requires '{}', which is not on PATH", tool));
+        }
+
+        warnings
+    }
+
+    /// Names of entries that already exist in `config`.
+    pub fn collisions(&self, config: &Config) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|entry| config.entries.iter().any(|e| e.game_name == entry.game_name))
+            .map(|entry| entry.game_name.clone())
+            .collect()
+    }
+
+    /// Adds the bundle's entries to `config`, resolving name collisions with
+    /// `resolution`, along with the templates they extend and the resources
+    /// they hold. A resource the config already defines keeps its
+    /// definition; a template it defines differently is added under a free
+    /// name instead. Merged entries are flattened before merging.
+    pub fn import_into(self, config: &mut Config, resolution: Resolution) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        let mut held = Vec::new();
+        let mut extended = Vec::new();
+
+        let bundled = Config { templates: self.templates.clone(), ..Config::default() };
+        let template_names = template_names(&self.templates, config);
+        let flatten = |entry: Entry| match bundled.resolve_entry(&entry) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                log::warn!("Can't flatten '{}' from the bundle: {}", entry.game_name, e);
+                entry
+            }
+        };
+
+        for entry in self.entries {
+            let holds = flatten(entry.clone()).resources;
+            let Some(index) = config.entries.iter().position(|e| e.game_name == entry.game_name) else {
+                summary.added.push(entry.game_name.clone());
+                held.extend(holds);
+                extended.extend(entry.extends.clone());
+                config.entries.push(Entry { extends: rename_parent(&template_names, entry.extends), ..entry });
+                continue;
+            };
+
+            match resolution {
+                Resolution::Rename => {
+                    let game_name = free_name(config, &entry.game_name);
+                    summary.added.push(game_name.clone());
+                    held.extend(holds);
+                    extended.extend(entry.extends.clone());
+                    config.entries.push(Entry { game_name, extends: rename_parent(&template_names, entry.extends), ..entry });
+                }
+                Resolution::Merge => {
+                    summary.merged.push(entry.game_name.clone());
+                    held.extend(holds);
+                    merge_entry(&mut config.entries[index], flatten(entry));
+                }
+                Resolution::Skip => summary.skipped.push(entry.game_name),
+            }
+        }
+
+        // The templates added entries extend, and their parents
+        let mut needed = BTreeSet::new();
+        while let Some(name) = extended.pop() {
+            if let Some(template) = self.templates.get(&name) {
+                if needed.insert(name) {
+                    extended.extend(template.extends.clone());
+                }
+            }
+        }
+        for name in needed {
+            let new_name = &template_names[&name];
+            if config.templates.contains_key(new_name) {
+                continue;
+            }
+            let template = &self.templates[&name];
+            let extends = rename_parent(&template_names, template.extends.clone());
+            config.templates.insert(new_name.clone(), Template { extends, ..template.clone() });
+            summary.templates.push(new_name.clone());
+        }
+
+        for name in held {
+            let Some(resource) = self.resources.get(&name) else {
+                continue;
+            };
+            match config.resources.get(&name) {
+                None => {
+                    config.resources.insert(name.clone(), resource.clone());
+                    summary.resources.push(name);
+                }
+                Some(existing) if existing != resource => {
+                    log::warn!("Resource '{}' is already defined differently, keeping the existing definition.", name);
+                }
+                Some(_) => {}
+            }
+        }
+
+        summary
+    }
+}
+
+/// Writes the entries of `config` named in `game_names` to a bundle at `path`.
+/// The templates they extend and the resources they hold are copied along,
+/// so the bundle stands on its own. Templates stay separate rather than
+/// being flattened, which would pair end commands with the wrong start
+/// commands for rollback.
+pub fn export(config: &Config, game_names: &[String], meta: BundleMeta, path: &Path) -> Result<usize, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    let mut resources = BTreeMap::new();
+    let mut templates = BTreeMap::new();
+    for name in game_names {
+        let Some(entry) = config.entries.iter().find(|e| &e.game_name == name) else {
+            return Err(format!("no entry named '{}'", name).into());
+        };
+
+        for name in config.resolve_entry(entry)?.resources {
+            if let Some(resource) = config.resources.get(&name) {
+                resources.insert(name, resource.clone());
+            }
+        }
+        // Resolving succeeded, so the chain ends
+        let mut parent = entry.extends.clone();
+        while let Some(name) = parent {
+            let template = config.templates[&name].clone();
+            parent = template.extends.clone();
+            templates.insert(name, template);
+        }
+        entries.push(entry.clone());
+    }
+
+    let count = entries.len();
+    Bundle { resources, templates, ..Bundle::new(entries, meta) }.save(path)?;
+    Ok(count)
+}
+
+/// Imports `bundle` into the config file at `config_path` and saves it.
+/// Merged entries that came from `profiles.d` are saved back to their own file.
+pub fn import_into_file(bundle: Bundle, config_path: &str, resolution: Resolution) -> Result<ImportSummary, Box<dyn Error>> {
+    let mut config = Config::load_from_file(config_path).map_err(|e| e.to_string())?;
+    let summary = bundle.import_into(&mut config, resolution);
+
+    for entry in config.entries.iter().filter(|e| e.source.is_some() && summary.merged.contains(&e.game_name)) {
+        entry.save_to_source()?;
+    }
+    config.save_to_file(config_path)?;
+
+    Ok(summary)
+}
+
+/// Folds `incoming` into `existing`: its commands, resources, companions and
+/// `while_running` commands are appended unless already present, and its
+/// executable, match rules, grace period, timeout and vars fill in only what
+/// `existing` leaves unset.
+pub fn merge_entry(existing: &mut Entry, incoming: Entry) {
+    if existing.executable.trim().is_empty() {
+        existing.executable = incoming.executable;
+    }
+    if existing.match_rules.is_none() {
+        existing.match_rules = incoming.match_rules;
+    }
+    if existing.end_grace_seconds.is_none() {
+        existing.end_grace_seconds = incoming.end_grace_seconds;
+    }
+    if existing.command_timeout_seconds.is_none() {
+        existing.command_timeout_seconds = incoming.command_timeout_seconds;
+    }
+    for (name, value) in incoming.vars {
+        existing.vars.entry(name).or_insert(value);
+    }
+
+    for cmd in incoming.start_commands {
+        if !existing.start_commands.contains(&cmd) {
+            existing.start_commands.push(cmd);
+        }
+    }
+    for cmd in incoming.end_commands {
+        if !existing.end_commands.contains(&cmd) {
+            existing.end_commands.push(cmd);
+        }
+    }
+    for cmd in incoming.on_crash {
+        if !existing.on_crash.contains(&cmd) {
+            existing.on_crash.push(cmd);
+        }
+    }
+    for resource in incoming.resources {
+        if !existing.resources.contains(&resource) {
+            existing.resources.push(resource);
+        }
+    }
+    for companion in incoming.companions {
+        if !existing.companions.contains(&companion) {
+            existing.companions.push(companion);
+        }
+    }
+    for periodic in incoming.while_running {
+        if !existing.while_running.contains(&periodic) {
+            existing.while_running.push(periodic);
+        }
+    }
+}
+
+/// `name (imported)`, or `name (imported 2)` and so on if that is taken too.
+fn free_name(config: &Config, name: &str) -> String {
+    let taken = |candidate: &str| config.entries.iter().any(|e| e.game_name == candidate);
+
+    let mut candidate = format!("{} (imported)", name);
+    let mut n = 2;
+    while taken(&candidate) {
+        candidate = format!("{} (imported {})", name, n);
+        n += 1;
+    }
+    candidate
+}
+
+/// The name each of the bundle's `templates` is imported under: its own,
+/// unless `config` has a different template of that name, then a free one
+/// such as `gaming (imported)`. A template is only the same as the config's
+/// if its parents are too.
+fn template_names(templates: &BTreeMap<String, Template>, config: &Config) -> BTreeMap<String, String> {
+    let mut same: BTreeSet<&String> = templates
+        .iter()
+        .filter(|(name, template)| config.templates.get(*name) == Some(template))
+        .map(|(name, _)| name)
+        .collect();
+    loop {
+        let before = same.len();
+        let parents_same = |name: &String| match &templates[name].extends {
+            Some(parent) => !templates.contains_key(parent) || same.contains(parent),
+            None => true,
+        };
+        let kept: BTreeSet<&String> = same.iter().copied().filter(|name| parents_same(name)).collect();
+        same = kept;
+        if same.len() == before {
+            break;
+        }
+    }
+
+    let mut names = BTreeMap::new();
+    let mut taken: BTreeSet<String> = config.templates.keys().cloned().collect();
+    for name in templates.keys() {
+        let new_name = if same.contains(name) || !taken.contains(name) {
+            name.clone()
+        } else {
+            let mut candidate = format!("{} (imported)", name);
+            let mut n = 2;
+            while taken.contains(&candidate) || templates.contains_key(&candidate) {
+                candidate = format!("{} (imported {})", name, n);
+                n += 1;
+            }
+            candidate
+        };
+        taken.insert(new_name.clone());
+        names.insert(name.clone(), new_name);
+    }
+    names
+}
+
+/// `parent` under the name its template is imported as. Templates the
+/// bundle doesn't carry keep their name.
+fn rename_parent(names: &BTreeMap<String, String>, parent: Option<String>) -> Option<String> {
+    parent.map(|parent| names.get(&parent).cloned().unwrap_or(parent))
+}
+
+fn on_path(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandSpec;
+
+    fn config(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    const SOURCE: &str = r#"
+        [resources.governor]
+        acquire = ["powerprofilesctl set performance"]
+        release = ["powerprofilesctl set balanced"]
+
+        [resources.unused]
+        acquire = ["true"]
+
+        [templates.gaming]
+        resources = ["governor"]
+
+        [[entries]]
+        game_name = "Elden Ring"
+        executable = "eldenring.exe"
+        extends = "gaming"
+        resources = ["headset"]
+    "#;
+
+    #[test]
+    fn export_carries_the_resources_entries_hold() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("elden.toml");
+        export(&config(SOURCE), &["Elden Ring".to_string()], BundleMeta::default(), &path).unwrap();
+
+        let bundle = Bundle::load(&path).unwrap();
+        assert_eq!(bundle.entries[0].resources, vec!["headset"]);
+        // `governor` is held through the template, `headset` isn't defined in
+        // the source config, so there is nothing to carry for it
+        assert_eq!(bundle.resources.keys().collect::<Vec<_>>(), vec!["governor"]);
+
+        let mut target = Config::default();
+        let summary = bundle.import_into(&mut target, Resolution::Skip);
+        assert_eq!(summary.added, vec!["Elden Ring"]);
+        assert_eq!(summary.resources, vec!["governor"]);
+        assert_eq!(summary.templates, vec!["gaming"]);
+        assert_eq!(target.resources["governor"], config(SOURCE).resources["governor"]);
+    }
+
+    #[test]
+    fn import_keeps_existing_resource_definitions() {
+        let bundle = Bundle {
+            resources: config(SOURCE).resources,
+            ..Bundle::new(config(SOURCE).resolved().unwrap().entries, BundleMeta::default())
+        };
+        let mut target = config(r#"
+            [resources.governor]
+            acquire = ["cpupower frequency-set -g performance"]
+
+            [[entries]]
+            game_name = "Elden Ring"
+            executable = "eldenring.exe"
+            resources = ["gpu"]
+        "#);
+
+        let summary = bundle.import_into(&mut target, Resolution::Merge);
+        assert_eq!(summary.merged, vec!["Elden Ring"]);
+        assert!(summary.resources.is_empty());
+        assert_eq!(target.resources["governor"].acquire, vec![CommandSpec::from("cpupower frequency-set -g performance")]);
+        assert!(!target.resources.contains_key("unused"));
+        assert_eq!(target.entries[0].resources, vec!["gpu", "governor", "headset"]);
+    }
+
+    const TEMPLATES: &str = r#"
+        [templates.base]
+        start_commands = ["gamemoded -r"]
+        end_commands = ["pkill gamemoded"]
+
+        [templates.gaming]
+        extends = "base"
+        start_commands = ["pactl set-default-sink headset"]
+        end_commands = ["pactl set-default-sink speakers"]
+        on_crash = ["notify-send crashed"]
+
+        [templates.unused]
+        start_commands = ["true"]
+
+        [[entries]]
+        game_name = "Elden Ring"
+        executable = "eldenring.exe"
+        extends = "gaming"
+        on_failure = "rollback"
+        start_commands = ["powerprofilesctl set performance"]
+        end_commands = ["powerprofilesctl set balanced"]
+        on_crash = ["notify-send 'Elden Ring crashed'"]
+        companions = [{ name = "overlay", command = "mangohud-overlay", restart = true }]
+        while_running = [{ command = "pactl set-sink-volume @DEFAULT_SINK@ 80%", interval = 60 }]
+        vars = { sink = "headset" }
+    "#;
+
+    fn bundle_of(text: &str) -> Bundle {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("elden.toml");
+        export(&config(text), &["Elden Ring".to_string()], BundleMeta::default(), &path).unwrap();
+        Bundle::load(&path).unwrap()
+    }
+
+    /// Entries as they would be saved, since `Entry` has no `PartialEq`.
+    fn saved(entries: &[Entry]) -> Vec<String> {
+        entries.iter().map(|entry| toml::to_string(entry).unwrap()).collect()
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn round_trip_keeps_templates_hooks_and_rollback_pairs() {
+        let source = config(TEMPLATES);
+        for file in ["elden.toml", "elden.json"] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join(file);
+            export(&source, &["Elden Ring".to_string()], BundleMeta::default(), &path).unwrap();
+
+            let mut target = Config::default();
+            let summary = Bundle::load(&path).unwrap().import_into(&mut target, Resolution::Skip);
+            assert_eq!(summary.templates, names(&["base", "gaming"]), "{}", file);
+
+            assert_eq!(saved(&target.entries), saved(&source.entries), "{}", file);
+            assert_eq!(target.templates["base"], source.templates["base"], "{}", file);
+            assert_eq!(target.templates["gaming"], source.templates["gaming"], "{}", file);
+            assert!(!target.templates.contains_key("unused"), "{}", file);
+
+            let imported = target.resolve_entry(&target.entries[0]).unwrap();
+            let original = source.resolve_entry(&source.entries[0]).unwrap();
+            assert_eq!(saved(&[imported.clone()]), saved(&[original.clone()]), "{}", file);
+            assert_eq!(imported.undo, original.undo, "{}", file);
+        }
+    }
+
+    #[test]
+    fn identical_templates_are_shared_and_different_ones_renamed() {
+        let mut target = config(r#"
+            [templates.base]
+            start_commands = ["gamemoded -r"]
+            end_commands = ["pkill gamemoded"]
+
+            [templates.gaming]
+            start_commands = ["pactl set-default-sink hdmi"]
+        "#);
+
+        let summary = bundle_of(TEMPLATES).import_into(&mut target, Resolution::Skip);
+        assert_eq!(summary.templates, names(&["gaming (imported)"]));
+        assert_eq!(target.entries[0].extends.as_deref(), Some("gaming (imported)"));
+        assert_eq!(target.templates["gaming (imported)"].extends.as_deref(), Some("base"));
+        assert_eq!(target.templates["gaming"].start_commands, vec![CommandSpec::from("pactl set-default-sink hdmi")]);
+
+        let resolved = target.resolve_entry(&target.entries[0]).unwrap();
+        let source = config(TEMPLATES);
+        assert_eq!(saved(&[resolved]), saved(&[source.resolve_entry(&source.entries[0]).unwrap()]));
+    }
+
+    #[test]
+    fn a_template_with_a_renamed_parent_is_renamed_too() {
+        let mut target = config(r#"
+            [templates.base]
+            start_commands = ["true"]
+
+            [templates.gaming]
+            extends = "base"
+            start_commands = ["pactl set-default-sink headset"]
+            end_commands = ["pactl set-default-sink speakers"]
+            on_crash = ["notify-send crashed"]
+        "#);
+
+        let summary = bundle_of(TEMPLATES).import_into(&mut target, Resolution::Skip);
+        assert_eq!(summary.templates, names(&["base (imported)", "gaming (imported)"]));
+        assert_eq!(target.templates["gaming (imported)"].extends.as_deref(), Some("base (imported)"));
+    }
+
+    #[test]
+    fn colliding_names_are_renamed_merged_or_skipped() {
+        let existing = r#"
+            [[entries]]
+            game_name = "Elden Ring"
+            executable = ""
+            start_commands = ["powerprofilesctl set performance"]
+            vars = { sink = "speakers" }
+
+            [[entries]]
+            game_name = "Elden Ring (imported)"
+            executable = "eldenring.exe"
+        "#;
+        assert_eq!(bundle_of(TEMPLATES).collisions(&config(existing)), names(&["Elden Ring"]));
+
+        let mut renamed = config(existing);
+        let summary = bundle_of(TEMPLATES).import_into(&mut renamed, Resolution::Rename);
+        assert_eq!(summary.added, names(&["Elden Ring (imported 2)"]));
+        assert_eq!(renamed.entries.len(), 3);
+        assert_eq!(saved(&renamed.entries[..2]), saved(&config(existing).entries));
+        assert_eq!(renamed.entries[2].extends.as_deref(), Some("gaming"));
+
+        let mut skipped = config(existing);
+        let summary = bundle_of(TEMPLATES).import_into(&mut skipped, Resolution::Skip);
+        assert_eq!(summary, ImportSummary { skipped: names(&["Elden Ring"]), ..Default::default() });
+        assert_eq!(saved(&skipped.entries), saved(&config(existing).entries));
+        assert!(skipped.templates.is_empty());
+
+        // Merging flattens the bundle's templates into the existing entry
+        let mut merged = config(existing);
+        let summary = bundle_of(TEMPLATES).import_into(&mut merged, Resolution::Merge);
+        assert_eq!(summary.merged, names(&["Elden Ring"]));
+        assert!(summary.templates.is_empty() && merged.templates.is_empty());
+        let entry = &merged.entries[0];
+        assert_eq!(entry.extends, None);
+        assert_eq!(entry.executable, "eldenring.exe");
+        assert_eq!(
+            entry.start_commands,
+            ["powerprofilesctl set performance", "gamemoded -r", "pactl set-default-sink headset"].map(CommandSpec::from)
+        );
+        assert_eq!(entry.on_crash, ["notify-send 'Elden Ring crashed'", "notify-send crashed"].map(CommandSpec::from));
+        assert_eq!(entry.companions.len(), 1);
+        assert_eq!(entry.vars["sink"], "speakers");
+    }
+}
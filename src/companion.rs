@@ -0,0 +1,307 @@
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use crate::config::Companion;
+use crate::executor;
+
+/// How often a companion is checked for having exited.
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Pause before a crashed companion is started again.
+const RESTART_DELAY: Duration = Duration::from_secs(2);
+
+/// Restarts per session before a crashing companion is given up on.
+const MAX_RESTARTS: u32 = 5;
+
+/// Every companion the service is supervising, for the tray.
+static STATUS: Mutex<Vec<CompanionStatus>> = Mutex::new(Vec::new());
+
+/// Bumped whenever `STATUS` changes, so the tray knows when to redraw.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Source of `Supervisor` ids. A restarted game gets a new supervisor before
+/// the old one may have stopped, so statuses are kept per supervisor rather
+/// than per game.
+static NEXT_SUPERVISOR: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompanionState {
+    Running { pid: u32 },
+    /// About to start, or crashed and waiting out `RESTART_DELAY`.
+    Starting,
+    /// Exited on its own and won't be started again this session.
+    Exited { code: Option<i32> },
+    /// Could not be started at all.
+    Failed(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompanionStatus {
+    /// The `Supervisor` running this companion.
+    supervisor: u64,
+    pub game_name: String,
+    /// Position in the entry's `companions`, since names may repeat.
+    pub index: usize,
+    pub name: String,
+    pub state: CompanionState,
+    pub restarts: u32,
+}
+
+impl std::fmt::Display for CompanionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.state {
+            CompanionState::Running { pid } => write!(f, "{}: running (PID {})", self.name, pid)?,
+            CompanionState::Starting => write!(f, "{}: starting", self.name)?,
+            CompanionState::Exited { code: Some(code) } => write!(f, "{}: exited ({})", self.name, code)?,
+            CompanionState::Exited { code: None } => write!(f, "{}: killed", self.name)?,
+            CompanionState::Failed(e) => write!(f, "{}: failed to start ({})", self.name, e)?,
+        }
+        if self.restarts > 0 {
+            write!(f, ", restarted {}×", self.restarts)?;
+        }
+        Ok(())
+    }
+}
+
+/// Companions of running sessions for `game_name`.
+pub fn statuses(game_name: &str) -> Vec<CompanionStatus> {
+    STATUS
+        .lock()
+        .map(|status| status.iter().filter(|s| s.game_name == game_name).cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Changes whenever any companion's status does.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Where a supervised companion reports its status.
+struct Slot {
+    supervisor: u64,
+    game_name: String,
+    index: usize,
+    name: String,
+}
+
+fn set_status(slot: &Slot, state: CompanionState, restarts: u32) {
+    let Ok(mut status) = STATUS.lock() else {
+        return;
+    };
+
+    let new = CompanionStatus {
+        supervisor: slot.supervisor,
+        game_name: slot.game_name.clone(),
+        index: slot.index,
+        name: slot.name.clone(),
+        state,
+        restarts,
+    };
+    match status.iter_mut().find(|s| s.supervisor == slot.supervisor && s.index == slot.index) {
+        Some(old) => *old = new,
+        None => status.push(new),
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+fn clear_status(supervisor: u64) {
+    if let Ok(mut status) = STATUS.lock() {
+        status.retain(|s| s.supervisor != supervisor);
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Runs the companions of one session, each in its own process group on its
+/// own thread, until `stop` is called.
+pub struct Supervisor {
+    id: u64,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    pub fn start(game_name: &str, companions: &[Companion], env: &[(String, String)]) -> Self {
+        let id = NEXT_SUPERVISOR.fetch_add(1, Ordering::Relaxed);
+        let stop = Arc::new(AtomicBool::new(false));
+        let slots: Vec<Slot> = companions
+            .iter()
+            .enumerate()
+            .map(|(index, companion)| Slot { supervisor: id, game_name: game_name.to_string(), index, name: companion.label() })
+            .collect();
+
+        // Register every companion first, so their order in the tray is fixed
+        for slot in &slots {
+            set_status(slot, CompanionState::Starting, 0);
+        }
+
+        let threads = slots
+            .into_iter()
+            .zip(companions)
+            .map(|(slot, companion)| {
+                let companion = companion.clone();
+                let env = env.to_vec();
+                let stop = stop.clone();
+                thread::spawn(move || supervise(&slot, &companion, &env, &stop))
+            })
+            .collect();
+
+        Self { id, stop, threads }
+    }
+
+    /// Terminates every companion still running and waits until they have exited.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+        clear_status(self.id);
+    }
+}
+
+fn supervise(slot: &Slot, companion: &Companion, env: &[(String, String)], stop: &AtomicBool) {
+    let (game_name, name) = (&slot.game_name, &slot.name);
+    let mut restarts = 0;
+
+    loop {
+        let mut child = match executor::spawn(&companion.command, env) {
+            Ok(child) => child,
+            Err(e) => {
+                log::error!("❌ Failed to start companion '{}' for '{}': {}", name, game_name, e);
+                set_status(slot, CompanionState::Failed(e.to_string()), restarts);
+                return;
+            }
+        };
+        log::info!("🟢 Started companion '{}' for '{}' (PID {}).", name, game_name, child.id());
+        set_status(slot, CompanionState::Running { pid: child.id() }, restarts);
+
+        let Some(status) = wait_or_stop(&mut child, stop) else {
+            log::info!("Stopping companion '{}' for '{}'...", name, game_name);
+            executor::terminate(&mut child);
+            return;
+        };
+
+        let code = status.as_ref().ok().and_then(|status| status.code());
+        let crashed = !status.as_ref().is_ok_and(|status| status.success());
+        let status = match status {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        };
+        if !crashed || !companion.restart {
+            log::info!("Companion '{}' for '{}' exited ({}).", name, game_name, status);
+            set_status(slot, CompanionState::Exited { code }, restarts);
+            return;
+        }
+        if restarts >= MAX_RESTARTS {
+            log::error!("❌ Companion '{}' for '{}' crashed {} times, giving up.", name, game_name, restarts + 1);
+            set_status(slot, CompanionState::Exited { code }, restarts);
+            return;
+        }
+
+        restarts += 1;
+        log::warn!(
+            "💥 Companion '{}' for '{}' crashed ({}), restarting ({}/{})...",
+            name, game_name, status, restarts, MAX_RESTARTS
+        );
+        set_status(slot, CompanionState::Starting, restarts);
+
+        let resume = Instant::now() + RESTART_DELAY;
+        while Instant::now() < resume {
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            thread::sleep(CHECK_INTERVAL);
+        }
+    }
+}
+
+/// Waits for `child` to exit, or returns `None` once `stop` is set.
+fn wait_or_stop(child: &mut Child, stop: &AtomicBool) -> Option<std::io::Result<std::process::ExitStatus>> {
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(Ok(status)),
+            Ok(None) => thread::sleep(CHECK_INTERVAL),
+            Err(e) => return Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn companion(command: &str, restart: bool) -> Companion {
+        Companion { name: String::new(), command: command.into(), restart }
+    }
+
+    /// The states of `game_name`'s companions once `done` holds for them.
+    fn wait_for(game_name: &str, done: impl Fn(&[CompanionStatus]) -> bool) -> Vec<CompanionStatus> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let status = statuses(game_name);
+            if done(&status) || Instant::now() > deadline {
+                return status;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn running(status: &[CompanionStatus]) -> bool {
+        status.iter().all(|s| matches!(s.state, CompanionState::Running { .. }))
+    }
+
+    #[test]
+    fn companions_are_listed_in_order_from_the_start() {
+        let supervisor = Supervisor::start("Ordered", &[companion("sleep 30", false), companion("sleep 31", false)], &[]);
+
+        let names: Vec<(usize, String)> = statuses("Ordered").into_iter().map(|s| (s.index, s.name)).collect();
+        assert_eq!(names, [(0, "sleep".to_string()), (1, "sleep".to_string())]);
+        assert!(running(&wait_for("Ordered", running)));
+
+        supervisor.stop();
+        assert!(statuses("Ordered").is_empty());
+    }
+
+    #[test]
+    fn stopping_an_ended_session_keeps_the_restarted_ones_companions() {
+        let ended = Supervisor::start("Restarted", &[companion("sleep 30", false)], &[]);
+        wait_for("Restarted", running);
+        let restarted = Supervisor::start("Restarted", &[companion("sleep 31", false)], &[]);
+        wait_for("Restarted", |status| status.len() == 2 && running(status));
+
+        ended.stop();
+        let status = statuses("Restarted");
+        assert_eq!(status.len(), 1, "{:?}", status);
+        assert!(matches!(status[0].state, CompanionState::Running { .. }));
+
+        restarted.stop();
+        assert!(statuses("Restarted").is_empty());
+    }
+
+    #[test]
+    fn exited_companions_stay_listed_until_the_session_stops() {
+        let supervisor = Supervisor::start("Exiting", &[companion("exit 3", false)], &[]);
+        let status = wait_for("Exiting", |status| matches!(status[0].state, CompanionState::Exited { .. }));
+        assert_eq!((&status[0].state, status[0].restarts), (&CompanionState::Exited { code: Some(3) }, 0));
+
+        supervisor.stop();
+        assert!(statuses("Exiting").is_empty());
+    }
+
+    #[test]
+    fn crashed_companions_wait_to_be_restarted() {
+        let supervisor = Supervisor::start("Crashing", &[companion("exit 1", true)], &[]);
+        let status = wait_for("Crashing", |status| status[0].restarts == 1);
+        assert_eq!((&status[0].state, status[0].restarts), (&CompanionState::Starting, 1));
+
+        // Stopping doesn't wait out the restart delay
+        let stopping = Instant::now();
+        supervisor.stop();
+        assert!(stopping.elapsed() < RESTART_DELAY);
+        assert!(statuses("Crashing").is_empty());
+    }
+}
@@ -1,10 +1,14 @@
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Deserializer};
 use toml::ser;
 use std::process::Command;
-use std::{fs, io};
+use std::{fs, io, thread};
+use std::io::Write;
 use std::error::Error;
 use once_cell::sync::Lazy;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use crate::migration::{self, CONFIG_VERSION};
 #[cfg(windows)]
 use std::ffi::OsStr;
 #[cfg(windows)]
@@ -93,60 +97,1101 @@ pub static GAMEMON_CONFIG_FILE: Lazy<PathBuf> = Lazy::new(|| {
     GAMEMON_CONFIG_DIR.join("config.toml")
 });
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+/// Drop-in directory with one entry per `*.toml` file, merged into the config on load.
+pub static GAMEMON_PROFILES_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    profiles_dir_for(&GAMEMON_CONFIG_FILE)
+});
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
+    /// Schema version, see `migration::CONFIG_VERSION`.
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub service: ServiceConfig,
+    #[serde(default, skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Named `[resources.<name>]` shared by overlapping sessions.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<String, Resource>,
+    /// Named `[templates.<name>]` that entries can `extends`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub templates: BTreeMap<String, Template>,
+    #[serde(default)]
     pub entries: Vec<Entry>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            settings: Settings::default(),
+            service: ServiceConfig::default(),
+            hooks: Hooks::default(),
+            resources: BTreeMap::new(),
+            templates: BTreeMap::new(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// User preferences from the `[settings]` table, shared by the GUI, tray and service.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub theme: ThemeType,
+    pub log_level: LogLevel,
+    pub notifications: NotificationSettings,
+    pub updates: UpdateSettings,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeType {
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    pub const ALL: [LogLevel; 4] = [LogLevel::Error, LogLevel::Warn, LogLevel::Info, LogLevel::Debug];
+
+    pub fn filter(self) -> log::LevelFilter {
+        match self {
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+        }
+    }
+}
+
+/// Which desktop notifications the service shows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct NotificationSettings {
+    /// Master switch for every notification below.
+    pub enabled: bool,
+    pub session_start: bool,
+    pub session_end: bool,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            session_start: false,
+            session_end: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct UpdateSettings {
+    /// Check for updates when the GUI opens and every
+    /// `[service] update_check_interval` seconds in the service.
+    pub auto_check: bool,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        Self { auto_check: true }
+    }
+}
+
+/// Watchdog settings from the `[service]` table. Re-read on every scan,
+/// so edits apply without restarting the service.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ServiceConfig {
+    /// Seconds between scans of the process table.
+    pub poll_interval: u64,
+    /// Seconds between update checks; 0 disables them.
+    pub update_check_interval: u64,
+    /// Poll at `idle_poll_interval` while no game runs and at
+    /// `fast_poll_interval` while a session waits out its grace period.
+    pub adaptive: bool,
+    pub idle_poll_interval: u64,
+    pub fast_poll_interval: u64,
+    /// Seconds a game may be gone before its session ends and end commands run.
+    /// A game that comes back within this window resumes its session.
+    pub end_grace_seconds: u64,
+    /// Seconds a command may run before it is stopped; 0 means no limit.
+    pub command_timeout_seconds: u64,
+    /// How the watchdog notices processes starting and exiting.
+    pub backend: WatchBackend,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: 5,
+            update_check_interval: 600,
+            adaptive: false,
+            idle_poll_interval: 15,
+            fast_poll_interval: 1,
+            end_grace_seconds: 0,
+            command_timeout_seconds: 0,
+            backend: WatchBackend::Poll,
+        }
+    }
+}
+
+impl ServiceConfig {
+    /// How long to wait before the next scan. `idle` means no session is
+    /// running, `ending` that a session is inside its grace period.
+    pub fn poll_timeout(&self, idle: bool, ending: bool) -> Duration {
+        let secs = match (self.adaptive, idle, ending) {
+            (false, _, _) => self.poll_interval,
+            (true, _, true) => self.fast_poll_interval,
+            (true, true, false) => self.idle_poll_interval,
+            (true, false, false) => self.poll_interval,
+        };
+        Duration::from_secs(secs.max(1))
+    }
+}
+
+/// Global commands from the `[hooks]` table. Session hooks wrap every
+/// entry's own commands: first-session and any-start hooks run before its
+/// start commands, any-end and last-session hooks after its end commands.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Hooks {
+    /// When a session starts while no other session is running.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_first_session_start: Vec<CommandSpec>,
+    /// When any session starts.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_any_start: Vec<CommandSpec>,
+    /// When any session ends.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_any_end: Vec<CommandSpec>,
+    /// When any game crashes, after its entry's own `on_crash`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_crash: Vec<CommandSpec>,
+    /// When the last running session ends.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_last_session_end: Vec<CommandSpec>,
+    /// When the service starts monitoring.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_service_start: Vec<CommandSpec>,
+    /// When the service shuts down.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_service_stop: Vec<CommandSpec>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchBackend {
+    /// Rescan the process table on a fixed interval.
+    #[default]
+    Poll,
+    /// Wake on exec/exit events from the kernel proc connector and pidfds,
+    /// falling back to polling for whatever isn't permitted.
+    Events,
+}
+
+/// Every field has a default so documents missing newer keys still load.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct Entry {
     pub game_name: String,
+    /// Name of a template in `[templates]` to inherit commands from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
     pub executable: String,
-    pub start_commands: Vec<String>,
-    pub end_commands: Vec<String>,
+    pub start_commands: Vec<CommandSpec>,
+    pub end_commands: Vec<CommandSpec>,
+    /// Run before the end commands when the game crashed, see `session::ExitKind`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_crash: Vec<CommandSpec>,
+    /// How `start_commands`/`end_commands` combine with the template's.
+    #[serde(skip_serializing_if = "CommandMerge::is_default")]
+    pub merge: CommandMerge,
+    /// For each of `start_commands`, the end command that undoes it when a
+    /// start is rolled back. Filled in by `Config::resolve_entry`.
+    #[serde(skip)]
+    pub undo: Vec<Option<CommandSpec>>,
+    /// Whether each command list runs one command at a time or all at once.
+    #[serde(skip_serializing_if = "is_default")]
+    pub mode: RunMode,
+    /// What a failing start command does to the rest of the session's start.
+    #[serde(skip_serializing_if = "is_default")]
+    pub on_failure: FailurePolicy,
+    /// Names of `[resources]` held while the session runs. Also accepts a single `resource = "..."`.
+    #[serde(alias = "resource", deserialize_with = "one_or_many", skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<String>,
+    /// Background programs started after the start commands and stopped before the end commands.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<Companion>,
+    /// Commands repeated on a timer for as long as the session runs.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub while_running: Vec<PeriodicCommand>,
+    /// Overrides `[service] end_grace_seconds` for this entry.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_grace_seconds: Option<u64>,
+    /// Overrides `[service] command_timeout_seconds` for this entry's commands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_timeout_seconds: Option<u64>,
+    /// Custom `{name}` placeholders for this entry's commands, see `service::CommandContext`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    /// Optional `[entries.match]` block. When absent, `executable` is matched by name.
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    pub match_rules: Option<MatchRules>,
+    /// The `profiles.d` file this entry was loaded from, or `None` for config.toml.
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
 }
 
 impl Default for Entry {
     fn default() -> Self {
         Self {
             game_name: String::new(),
+            extends: None,
             executable: String::new(),
             start_commands: Vec::new(),
             end_commands: Vec::new(),
+            on_crash: Vec::new(),
+            merge: CommandMerge::default(),
+            undo: Vec::new(),
+            mode: RunMode::default(),
+            on_failure: FailurePolicy::default(),
+            resources: Vec::new(),
+            companions: Vec::new(),
+            while_running: Vec::new(),
+            end_grace_seconds: None,
+            command_timeout_seconds: None,
+            vars: BTreeMap::new(),
+            match_rules: None,
+            source: None,
+        }
+    }
+}
+
+/// Shared commands that entries pull in with `extends`. A template may
+/// itself extend another one.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Template {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    pub start_commands: Vec<CommandSpec>,
+    pub end_commands: Vec<CommandSpec>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub on_crash: Vec<CommandSpec>,
+    #[serde(skip_serializing_if = "CommandMerge::is_default")]
+    pub merge: CommandMerge,
+    /// See `Entry::undo`.
+    #[serde(skip)]
+    pub undo: Vec<Option<CommandSpec>>,
+    #[serde(alias = "resource", deserialize_with = "one_or_many", skip_serializing_if = "Vec::is_empty")]
+    pub resources: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<Companion>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub while_running: Vec<PeriodicCommand>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_grace_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_timeout_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+}
+
+/// A command from the config: either a shell command line, run with
+/// `sh -c` as before, or a table naming the program and its arguments:
+///
+/// ```toml
+/// start_commands = [
+///     "notify-send 'Game on'",
+///     { program = "obs", args = ["--minimize"], cwd = "~/Videos", env = { QT_QPA_PLATFORM = "xcb" } },
+/// ]
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CommandSpec {
+    Shell(String),
+    Program(ProgramCommand),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProgramCommand {
+    /// Executable to run, looked up on `PATH`. With `shell = true`, a shell command line instead.
+    pub program: String,
+    /// Passed as-is, without shell quoting. With `shell = true` they become `$1`, `$2`, ...
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Working directory; a leading `~` is the home directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Extra environment variables.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub shell: bool,
+    /// Seconds the command may run before it is stopped, overriding the entry's limit. 0 means no limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl CommandSpec {
+    /// True for a command line or program that is empty or only whitespace.
+    pub fn is_blank(&self) -> bool {
+        match self {
+            CommandSpec::Shell(cmd) => cmd.trim().is_empty(),
+            CommandSpec::Program(cmd) => cmd.program.trim().is_empty(),
+        }
+    }
+
+    /// The command's time limit: its own `timeout_seconds` if set, where 0
+    /// lifts the limit, otherwise `default`.
+    pub fn timeout(&self, default: Option<Duration>) -> Option<Duration> {
+        match self {
+            CommandSpec::Program(ProgramCommand { timeout_seconds: Some(secs), .. }) => {
+                (*secs > 0).then(|| Duration::from_secs(*secs))
+            }
+            _ => default,
+        }
+    }
+
+    /// One line for the GUI's command editor: shell commands as they are,
+    /// program commands as a TOML inline table.
+    pub fn to_line(&self) -> String {
+        match self {
+            CommandSpec::Shell(cmd) => cmd.clone(),
+            CommandSpec::Program(cmd) => {
+                let mut fields = vec![format!("program = {}", toml_string(&cmd.program))];
+                if !cmd.args.is_empty() {
+                    let args: Vec<String> = cmd.args.iter().map(|arg| toml_string(arg)).collect();
+                    fields.push(format!("args = [{}]", args.join(", ")));
+                }
+                if let Some(cwd) = &cmd.cwd {
+                    fields.push(format!("cwd = {}", toml_string(cwd)));
+                }
+                if !cmd.env.is_empty() {
+                    let vars: Vec<String> = cmd
+                        .env
+                        .iter()
+                        .map(|(key, value)| format!("{} = {}", toml_key(key), toml_string(value)))
+                        .collect();
+                    fields.push(format!("env = {{ {} }}", vars.join(", ")));
+                }
+                if cmd.shell {
+                    fields.push("shell = true".to_string());
+                }
+                if let Some(timeout) = cmd.timeout_seconds {
+                    fields.push(format!("timeout_seconds = {}", timeout));
+                }
+                format!("{{ {} }}", fields.join(", "))
+            }
+        }
+    }
+
+    /// Reverse of `to_line`: a line starting with `{` that parses as an
+    /// inline table is a program command, anything else a shell command.
+    pub fn from_line(line: &str) -> Self {
+        #[derive(Deserialize)]
+        struct Line {
+            command: ProgramCommand,
+        }
+
+        let trimmed = line.trim();
+        if trimmed.starts_with('{') {
+            if let Ok(parsed) = toml::from_str::<Line>(&format!("command = {}", trimmed)) {
+                return CommandSpec::Program(parsed.command);
+            }
+        }
+        CommandSpec::Shell(line.to_string())
+    }
+}
+
+/// A basic TOML string that always fits on one line.
+fn toml_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+impl std::fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_line())
+    }
+}
+
+impl From<&str> for CommandSpec {
+    fn from(cmd: &str) -> Self {
+        CommandSpec::Shell(cmd.to_string())
+    }
+}
+
+/// A background program that lives exactly as long as the game's session,
+/// such as `obs --minimize`. A plain command is accepted as shorthand for
+/// `{ command = ... }`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "CompanionDef")]
+pub struct Companion {
+    /// Shown in the tray; defaults to the program's name.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub name: String,
+    pub command: CommandSpec,
+    /// Start it again if it exits unsuccessfully while the game still runs.
+    #[serde(skip_serializing_if = "is_false")]
+    pub restart: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CompanionDef {
+    Full {
+        #[serde(default)]
+        name: String,
+        command: CommandSpec,
+        #[serde(default)]
+        restart: bool,
+    },
+    Command(CommandSpec),
+}
+
+impl From<CompanionDef> for Companion {
+    fn from(def: CompanionDef) -> Self {
+        match def {
+            CompanionDef::Full { name, command, restart } => Companion { name, command, restart },
+            CompanionDef::Command(command) => Companion { name: String::new(), command, restart: false },
+        }
+    }
+}
+
+impl Companion {
+    /// `name`, or the first word of the command.
+    pub fn label(&self) -> String {
+        if !self.name.trim().is_empty() {
+            return self.name.clone();
+        }
+        let program = match &self.command {
+            CommandSpec::Shell(cmd) => cmd.split_whitespace().next().unwrap_or_default(),
+            CommandSpec::Program(cmd) => cmd.program.split_whitespace().next().unwrap_or_default(),
+        };
+        Path::new(program).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+    }
+}
+
+/// A command the service repeats while the game runs, e.g. to re-apply a
+/// fan curve that something else keeps resetting. The first run is one
+/// `interval` after the session starts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PeriodicCommand {
+    pub command: CommandSpec,
+    /// Seconds between runs.
+    pub interval: u64,
+}
+
+impl PeriodicCommand {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+}
+
+/// Something several games may need at once, such as the performance
+/// governor. `acquire` runs when the first session holding it starts and
+/// `release` when the last one ends.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Resource {
+    pub acquire: Vec<CommandSpec>,
+    pub release: Vec<CommandSpec>,
+}
+
+/// Accepts either a single string or a list of them.
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// Per list, where an entry's commands go relative to its template's.
+/// By default start commands run after the template's and end commands
+/// before them, so the entry's own setup is undone first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct CommandMerge {
+    pub start: MergeMode,
+    pub end: MergeMode,
+}
+
+impl Default for CommandMerge {
+    fn default() -> Self {
+        Self { start: MergeMode::Append, end: MergeMode::Prepend }
+    }
+}
+
+impl CommandMerge {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMode {
+    /// The entry's commands run before the template's.
+    Prepend,
+    /// The entry's commands run after the template's.
+    Append,
+    /// Only the entry's commands run.
+    Override,
+}
+
+impl MergeMode {
+    fn apply<T: Clone>(self, inherited: &[T], own: &[T]) -> Vec<T> {
+        match self {
+            MergeMode::Prepend => own.iter().chain(inherited).cloned().collect(),
+            MergeMode::Append => inherited.iter().chain(own).cloned().collect(),
+            MergeMode::Override => own.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunMode {
+    /// Each command starts once the one before it has exited.
+    #[default]
+    Sequential,
+    /// All commands start together; the list is done when the last exits.
+    Parallel,
+}
+
+/// What happens when a start command fails or times out. End commands
+/// always all run, so cleanup is never cut short.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Run the remaining start commands anyway.
+    #[default]
+    Continue,
+    /// Skip the remaining start commands. In `RunMode::Parallel` they have
+    /// all started by then, so there is nothing to skip.
+    Abort,
+    /// Skip the remaining start commands and undo the ones that succeeded by
+    /// running their end commands, see `Entry::undo`.
+    Rollback,
+}
+
+/// Inherited items followed by the entry's own, without duplicates.
+fn merge_unique<T: Clone + PartialEq>(inherited: &[T], own: &[T]) -> Vec<T> {
+    let mut merged = inherited.to_vec();
+    for item in own {
+        if !merged.contains(item) {
+            merged.push(item.clone());
         }
     }
+    merged
+}
+
+/// The undo commands of `start` merged with the inherited ones the same way
+/// the start commands are. Within one entry or template, `end[i]` undoes
+/// `start[i]`. Overriding the end commands drops the inherited undos along
+/// with the end commands they came from.
+fn merge_undo(
+    merge: CommandMerge,
+    inherited: &[Option<CommandSpec>],
+    start: &[CommandSpec],
+    end: &[CommandSpec],
+) -> Vec<Option<CommandSpec>> {
+    let inherited = match merge.end {
+        MergeMode::Override => vec![None; inherited.len()],
+        _ => inherited.to_vec(),
+    };
+    let own: Vec<Option<CommandSpec>> = (0..start.len()).map(|i| end.get(i).cloned()).collect();
+    merge.start.apply(&inherited, &own)
+}
+
+/// Inherited variables, with the entry's own taking precedence.
+fn merge_vars(inherited: &BTreeMap<String, String>, own: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut merged = inherited.clone();
+    merged.extend(own.iter().map(|(name, value)| (name.clone(), value.clone())));
+    merged
+}
+
+/// Why an entry's `extends` chain can't be flattened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// `referenced_by` extends a template that doesn't exist.
+    Missing { name: String, referenced_by: String },
+    /// Templates that extend each other in a loop, starting and ending with the same name.
+    Cycle(Vec<String>),
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::Missing { name, referenced_by } => {
+                write!(f, "'{}' extends unknown template '{}'", referenced_by, name)
+            }
+            TemplateError::Cycle(chain) => write!(f, "templates extend each other in a loop: {}", chain.join(" -> ")),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+/// Rules deciding which processes belong to an entry.
+/// Every rule that is set must hold; any `exclude` hit rejects the process.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct MatchRules {
+    /// Exact process name (`comm`), case-insensitive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Glob matched against the absolute executable path, e.g. `/opt/games/**/game.x86_64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Regex matched against the command line, arguments joined by spaces.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cmdline: Option<String>,
+    /// Process name of the parent process, e.g. `steam`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Steam AppID, read from `SteamAppId`/`SteamGameId` or the reaper's `AppId=` argument.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_id: Option<u32>,
+    /// Regexes matched against the command line that reject an otherwise matching process.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+}
+
+impl MatchRules {
+    /// True when no positive rule is set, so the entry falls back to `executable`.
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.path.is_none()
+            && self.cmdline.is_none()
+            && self.parent.is_none()
+            && self.app_id.is_none()
+    }
 }
 
 impl Config {
     // Use TOML to load the configuration from a file
+    //
+    // Reads under a shared lock and retries a few times if the file doesn't
+    // parse, in case it is caught mid-write by something that doesn't lock.
     pub fn load_from_file(file_path: &str) -> Result<Self, Box<dyn Error + Send>> {
-        let data = fs::read_to_string(file_path)
-            .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+        let mut attempt = 1;
+        loop {
+            let data = {
+                let _lock = ConfigLock::shared(Path::new(file_path))
+                    .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+                fs::read_to_string(file_path)
+                    .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?
+            };
 
+            match Self::parse(&data) {
+                Err(e) if attempt < LOAD_ATTEMPTS => {
+                    log::debug!("Config did not parse (attempt {}), retrying: {}", attempt, e);
+                    thread::sleep(LOAD_RETRY_DELAY);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+                Ok(mut config) => {
+                    let profiles = load_profiles(&profiles_dir_for(Path::new(file_path)))?;
+                    config.merge_profiles(profiles);
+                    return Ok(config);
+                }
+            }
+        }
+    }
+
+    /// Adds drop-in entries. A later file replaces an earlier one with the
+    /// same game name, and an entry in config.toml overrides them all.
+    fn merge_profiles(&mut self, profiles: Vec<Entry>) {
+        let mut merged: Vec<Entry> = Vec::new();
+        for profile in profiles {
+            match merged.iter_mut().find(|e| e.game_name == profile.game_name) {
+                Some(existing) => *existing = profile,
+                None => merged.push(profile),
+            }
+        }
+
+        for profile in merged {
+            if self.entries.iter().any(|e| e.game_name == profile.game_name) {
+                log::debug!("config.toml overrides profile '{}' from {:?}", profile.game_name, profile.source);
+            } else {
+                self.entries.push(profile);
+            }
+        }
+    }
+
+    pub(crate) fn parse(data: &str) -> Result<Self, Box<dyn Error + Send>> {
         if data.trim().is_empty() {
             log::info!("Config file is empty. Initializing a new empty config.");
             return Ok(Config::default());
         }
 
-        let config: Config = toml::from_str(&data)
-            .map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
-        
+        let mut doc: toml::Table = data.parse()
+            .map_err(|e| ConfigError::from_toml(data, &e))?;
+
+        // Current files are parsed straight from the text, so errors keep their position
+        if migration::version_of(&doc) == CONFIG_VERSION {
+            return toml::from_str(data)
+                .map_err(|e| ConfigError::from_toml(data, &e));
+        }
+
+        migration::migrate(&mut doc)?;
+        let config: Config = doc.try_into()
+            .map_err(|e| ConfigError::from_toml(data, &e))?;
+
         Ok(config)
     }
 
+    /// A copy with every entry's `extends` chain flattened into plain
+    /// command lists, as the watchdog runs them. Fails on the first entry
+    /// that extends a missing template or a loop.
+    pub fn resolved(&self) -> Result<Config, TemplateError> {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| self.resolve_entry(entry))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Config { templates: BTreeMap::new(), entries, ..self.clone() })
+    }
+
+    /// `entry` with its template chain flattened.
+    pub fn resolve_entry(&self, entry: &Entry) -> Result<Entry, TemplateError> {
+        let Some(parent) = &entry.extends else {
+            return Ok(Entry { undo: merge_undo(entry.merge, &[], &entry.start_commands, &entry.end_commands), ..entry.clone() });
+        };
+
+        let inherited = self.resolve_template(parent, &entry.game_name, &mut Vec::new())?;
+        Ok(Entry {
+            extends: None,
+            start_commands: entry.merge.start.apply(&inherited.start_commands, &entry.start_commands),
+            end_commands: entry.merge.end.apply(&inherited.end_commands, &entry.end_commands),
+            undo: merge_undo(entry.merge, &inherited.undo, &entry.start_commands, &entry.end_commands),
+            on_crash: MergeMode::Prepend.apply(&inherited.on_crash, &entry.on_crash),
+            merge: CommandMerge::default(),
+            resources: merge_unique(&inherited.resources, &entry.resources),
+            companions: merge_unique(&inherited.companions, &entry.companions),
+            while_running: merge_unique(&inherited.while_running, &entry.while_running),
+            end_grace_seconds: entry.end_grace_seconds.or(inherited.end_grace_seconds),
+            command_timeout_seconds: entry.command_timeout_seconds.or(inherited.command_timeout_seconds),
+            vars: merge_vars(&inherited.vars, &entry.vars),
+            ..entry.clone()
+        })
+    }
+
+    /// Template `name` with its own parents flattened. `stack` holds the
+    /// templates being resolved, to catch loops.
+    pub fn resolve_template(&self, name: &str, referenced_by: &str, stack: &mut Vec<String>) -> Result<Template, TemplateError> {
+        if let Some(start) = stack.iter().position(|n| n == name) {
+            let mut chain = stack[start..].to_vec();
+            chain.push(name.to_string());
+            return Err(TemplateError::Cycle(chain));
+        }
+
+        let Some(template) = self.templates.get(name) else {
+            return Err(TemplateError::Missing { name: name.to_string(), referenced_by: referenced_by.to_string() });
+        };
+        let Some(parent) = &template.extends else {
+            let undo = merge_undo(template.merge, &[], &template.start_commands, &template.end_commands);
+            return Ok(Template { undo, ..template.clone() });
+        };
+
+        stack.push(name.to_string());
+        let inherited = self.resolve_template(parent, name, stack)?;
+        stack.pop();
+
+        Ok(Template {
+            extends: None,
+            start_commands: template.merge.start.apply(&inherited.start_commands, &template.start_commands),
+            end_commands: template.merge.end.apply(&inherited.end_commands, &template.end_commands),
+            on_crash: MergeMode::Prepend.apply(&inherited.on_crash, &template.on_crash),
+            merge: CommandMerge::default(),
+            undo: merge_undo(template.merge, &inherited.undo, &template.start_commands, &template.end_commands),
+            resources: merge_unique(&inherited.resources, &template.resources),
+            companions: merge_unique(&inherited.companions, &template.companions),
+            while_running: merge_unique(&inherited.while_running, &template.while_running),
+            end_grace_seconds: template.end_grace_seconds.or(inherited.end_grace_seconds),
+            command_timeout_seconds: template.command_timeout_seconds.or(inherited.command_timeout_seconds),
+            vars: merge_vars(&inherited.vars, &template.vars),
+        })
+    }
+
+    /// How long a session of `entry` may be gone before it ends.
+    pub fn end_grace(&self, entry: &Entry) -> Duration {
+        Duration::from_secs(entry.end_grace_seconds.unwrap_or(self.service.end_grace_seconds))
+    }
+
+    /// Time limit for the commands of `entry`, or for global hooks without one.
+    /// `None` means commands may run for as long as they like.
+    pub fn command_timeout(&self, entry: Option<&Entry>) -> Option<Duration> {
+        let secs = entry
+            .and_then(|entry| entry.command_timeout_seconds)
+            .unwrap_or(self.service.command_timeout_seconds);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
     // Use TOML to save the configuration to a file
+    //
+    // Entries loaded from profiles.d are left out; they are saved to their
+    // own files with `Entry::save_to_source`.
     pub fn save_to_file(&self, file_path: &str) -> Result<(), Box<dyn Error>> {
+        let mut config = self.clone();
+        config.entries.retain(|entry| entry.source.is_none());
+        let data = ser::to_string_pretty(&config)?;
+        write_atomic(Path::new(file_path), data.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// Writes a drop-in entry back to the `profiles.d` file it came from.
+    pub fn save_to_source(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.source else {
+            return Err(Box::new(io::Error::new(io::ErrorKind::NotFound, "entry was not loaded from profiles.d")));
+        };
         let data = ser::to_string_pretty(self)?;
-        fs::write(file_path, data)?;
+        replace_file(path, data.as_bytes())?;
         Ok(())
     }
 }
 
+/// The `profiles.d` directory next to a config file.
+pub fn profiles_dir_for(config_file: &Path) -> PathBuf {
+    config_file.with_file_name("profiles.d")
+}
+
+/// The `*.toml` files in `dir` in load order; none if it doesn't exist.
+pub fn profile_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut files: Vec<PathBuf> = read_dir
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads every `*.toml` file in `dir` as one entry, in file name order.
+/// A missing directory has no profiles; a file that doesn't parse fails the
+/// whole load, like an error in config.toml would.
+pub fn load_profiles(dir: &Path) -> Result<Vec<Entry>, Box<dyn Error + Send>> {
+    let mut profiles = Vec::new();
+    for path in profile_files(dir).map_err(|e| Box::new(e) as Box<dyn Error + Send>)? {
+        let data = fs::read_to_string(&path).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+        let mut entry: Entry = toml::from_str(&data).map_err(|e| {
+            let mut error = ConfigError::new(&data, &e);
+            error.path = Some(path.clone());
+            Box::new(error) as Box<dyn Error + Send>
+        })?;
+
+        // A profile without a name is named after its file
+        if entry.game_name.trim().is_empty() {
+            if let Some(stem) = path.file_stem() {
+                entry.game_name = stem.to_string_lossy().into_owned();
+            }
+        }
+        entry.source = Some(path);
+        profiles.push(entry);
+    }
+
+    Ok(profiles)
+}
+
+/// A config file that doesn't parse, with the position of the problem when
+/// TOML reports one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Set for errors in a `profiles.d` file rather than config.toml.
+    pub path: Option<PathBuf>,
+    pub message: String,
+    /// 1-based line and column.
+    pub location: Option<(usize, usize)>,
+}
+
+impl ConfigError {
+    pub fn new(data: &str, e: &toml::de::Error) -> Self {
+        Self {
+            path: None,
+            message: e.message().trim().lines().collect::<Vec<_>>().join("; "),
+            location: e.span().map(|span| line_col(data, span.start)),
+        }
+    }
+
+    fn from_toml(data: &str, e: &toml::de::Error) -> Box<dyn Error + Send> {
+        Box::new(Self::new(data, e))
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}: ", path.display())?;
+        }
+        match self.location {
+            Some((line, column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// 1-based line and column of byte `offset` in `text`.
+pub fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+const LOAD_ATTEMPTS: u32 = 3;
+const LOAD_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Advisory lock on `<config>.lock`, shared by the GUI, tray and service.
+/// A separate lock file keeps working across the rename in `write_atomic`.
+/// Released when dropped.
+pub struct ConfigLock {
+    _file: fs::File,
+}
+
+impl ConfigLock {
+    pub fn shared(path: &Path) -> io::Result<Self> {
+        Self::acquire(path, false)
+    }
+
+    pub fn exclusive(path: &Path) -> io::Result<Self> {
+        Self::acquire(path, true)
+    }
+
+    fn acquire(path: &Path, exclusive: bool) -> io::Result<Self> {
+        let mut lock_name = path.as_os_str().to_owned();
+        lock_name.push(".lock");
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(PathBuf::from(lock_name))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+            let op = if exclusive { libc::LOCK_EX } else { libc::LOCK_SH };
+            loop {
+                if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+                    break;
+                }
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = exclusive;
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Replaces `path` with `data` so readers see either the old or the new
+/// file, never a partial one: write a temp file in the same directory,
+/// fsync it and rename it over the original, under the exclusive lock.
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+    let _lock = ConfigLock::exclusive(path)?;
+    replace_file(path, data)
+}
+
+/// The unlocked part of `write_atomic`, also used for `profiles.d` files so
+/// no lock files end up next to shared profiles.
+fn replace_file(path: &Path, data: &[u8]) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    tmp.write_all(data)?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
 pub fn ensure_paths_exist() -> io::Result<()> {
     let paths_to_create = [
         &*GAMEMON_DIR,
         &*GAMEMON_RESOURCE_DIR,
         &*GAMEMON_CONFIG_DIR,
+        &*GAMEMON_PROFILES_DIR,
     ];
 
     for path in paths_to_create {
@@ -243,3 +1288,177 @@ pub fn check_for_updates(arg: String) -> Result<(), Box<dyn Error>> {
             .map_err(|e| Box::new(e) as Box<dyn Error>) // Convert to `Box<dyn Error>`
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> Config {
+        toml::from_str(text).unwrap()
+    }
+
+    fn resolve(config: &Config, game_name: &str) -> Result<Entry, TemplateError> {
+        let entry = config.entries.iter().find(|e| e.game_name == game_name).unwrap();
+        config.resolve_entry(entry)
+    }
+
+    fn commands(lines: &[&str]) -> Vec<CommandSpec> {
+        lines.iter().map(|line| CommandSpec::from(*line)).collect()
+    }
+
+    const LAYERED: &str = r#"
+        [templates.base]
+        start_commands = ["governor performance"]
+        end_commands = ["governor powersave"]
+        resources = ["gpu"]
+        end_grace_seconds = 5
+        vars = { sink = "speakers", card = "card0" }
+
+        [templates.gaming]
+        extends = "base"
+        start_commands = ["sink headset"]
+        end_commands = ["sink speakers"]
+        merge = { start = "prepend" }
+        resources = ["gpu", "audio"]
+        vars = { sink = "headset" }
+
+        [[entries]]
+        game_name = "Appended"
+        executable = "a"
+        extends = "gaming"
+        start_commands = ["pause syncthing"]
+        end_commands = ["resume syncthing"]
+        resources = ["audio", "network"]
+        vars = { card = "card1" }
+
+        [[entries]]
+        game_name = "Reversed"
+        executable = "r"
+        extends = "gaming"
+        start_commands = ["pause syncthing"]
+        end_commands = ["resume syncthing"]
+        merge = { start = "prepend", end = "append" }
+
+        [[entries]]
+        game_name = "Overridden"
+        executable = "o"
+        extends = "gaming"
+        start_commands = ["only this"]
+        merge = { start = "override", end = "override" }
+        end_grace_seconds = 30
+    "#;
+
+    #[test]
+    fn templates_merge_through_every_level() {
+        let config = config(LAYERED);
+
+        // gaming prepends to base, then the entry appends to gaming
+        let entry = resolve(&config, "Appended").unwrap();
+        assert_eq!(entry.start_commands, commands(&["sink headset", "governor performance", "pause syncthing"]));
+        assert_eq!(entry.end_commands, commands(&["resume syncthing", "sink speakers", "governor powersave"]));
+        assert_eq!(entry.extends, None);
+        assert_eq!(entry.end_grace_seconds, Some(5));
+
+        let entry = resolve(&config, "Reversed").unwrap();
+        assert_eq!(entry.start_commands, commands(&["pause syncthing", "sink headset", "governor performance"]));
+        assert_eq!(entry.end_commands, commands(&["sink speakers", "governor powersave", "resume syncthing"]));
+
+        let entry = resolve(&config, "Overridden").unwrap();
+        assert_eq!(entry.start_commands, commands(&["only this"]));
+        assert!(entry.end_commands.is_empty());
+        assert_eq!(entry.end_grace_seconds, Some(30));
+    }
+
+    #[test]
+    fn resources_and_vars_merge_without_duplicates() {
+        let config = config(LAYERED);
+
+        let entry = resolve(&config, "Appended").unwrap();
+        assert_eq!(entry.resources, vec!["gpu", "audio", "network"]);
+        assert_eq!(entry.vars.get("sink").map(String::as_str), Some("headset"));
+        assert_eq!(entry.vars.get("card").map(String::as_str), Some("card1"));
+
+        let entry = resolve(&config, "Overridden").unwrap();
+        assert_eq!(entry.resources, vec!["gpu", "audio"]);
+
+        assert_eq!(merge_unique(&["a", "b"], &["b", "c", "a", "d"]), vec!["a", "b", "c", "d"]);
+        assert_eq!(merge_unique::<&str>(&[], &[]), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn entries_without_a_template_resolve_to_themselves() {
+        let config = config(LAYERED);
+        let entry = Entry { game_name: "Plain".to_string(), start_commands: commands(&["x"]), ..Entry::default() };
+        let resolved = config.resolve_entry(&entry).unwrap();
+        assert_eq!((resolved.game_name, resolved.start_commands), (entry.game_name, entry.start_commands));
+    }
+
+    #[test]
+    fn missing_parents_name_who_referenced_them() {
+        let config = config(r#"
+            [templates.orphan]
+            extends = "gone"
+
+            [[entries]]
+            game_name = "Lost"
+            executable = "l"
+            extends = "nowhere"
+
+            [[entries]]
+            game_name = "Deep"
+            executable = "d"
+            extends = "orphan"
+        "#);
+
+        assert_eq!(
+            resolve(&config, "Lost").unwrap_err(),
+            TemplateError::Missing { name: "nowhere".to_string(), referenced_by: "Lost".to_string() }
+        );
+        assert_eq!(
+            resolve(&config, "Deep").unwrap_err(),
+            TemplateError::Missing { name: "gone".to_string(), referenced_by: "orphan".to_string() }
+        );
+        assert!(config.resolved().is_err());
+    }
+
+    #[test]
+    fn cycles_report_the_whole_loop() {
+        let config = config(r#"
+            [templates.a]
+            extends = "b"
+            [templates.b]
+            extends = "c"
+            [templates.c]
+            extends = "a"
+            [templates.selfish]
+            extends = "selfish"
+            [templates.tail]
+            extends = "b"
+
+            [[entries]]
+            game_name = "Loop"
+            executable = "l"
+            extends = "a"
+
+            [[entries]]
+            game_name = "Self"
+            executable = "s"
+            extends = "selfish"
+
+            [[entries]]
+            game_name = "Tail"
+            executable = "t"
+            extends = "tail"
+        "#);
+
+        let chain = |names: &[&str]| TemplateError::Cycle(names.iter().map(|n| n.to_string()).collect());
+        assert_eq!(resolve(&config, "Loop").unwrap_err(), chain(&["a", "b", "c", "a"]));
+        assert_eq!(resolve(&config, "Self").unwrap_err(), chain(&["selfish", "selfish"]));
+        // A template leading into a loop isn't part of it
+        assert_eq!(resolve(&config, "Tail").unwrap_err(), chain(&["b", "c", "a", "b"]));
+        assert_eq!(
+            chain(&["a", "b", "a"]).to_string(),
+            "templates extend each other in a loop: a -> b -> a"
+        );
+    }
+}
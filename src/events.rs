@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+use crate::config::WatchBackend;
+use crate::session::ProcessExit;
+
+/// Blocks the watchdog between two scans of the process table.
+pub trait Waiter {
+    /// Waits up to `timeout`, returning early when a process may have started
+    /// or one of the `tracked` session PIDs exited.
+    fn wait(&mut self, tracked: &[u32], timeout: Duration);
+
+    /// True if process starts wake the waiter, so the watchdog may sleep
+    /// much longer than the poll interval while idle.
+    fn is_event_driven(&self) -> bool {
+        false
+    }
+
+    /// How `tracked` PIDs exited since the last call, where the backend
+    /// could see it. Polling can't, so by default nothing is known.
+    fn take_exits(&mut self) -> HashMap<u32, ProcessExit> {
+        HashMap::new()
+    }
+}
+
+/// The classic backend: sleep for the whole timeout.
+pub struct PollWaiter;
+
+impl Waiter for PollWaiter {
+    fn wait(&mut self, _tracked: &[u32], timeout: Duration) {
+        thread::sleep(timeout);
+    }
+}
+
+/// Returns the waiter for `backend`, falling back to polling when the
+/// event sources are unavailable.
+pub fn waiter_for(backend: WatchBackend) -> Box<dyn Waiter + Send> {
+    match backend {
+        WatchBackend::Poll => Box::new(PollWaiter),
+        WatchBackend::Events => event_waiter(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn event_waiter() -> Box<dyn Waiter + Send> {
+    Box::new(linux::EventWaiter::new())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn event_waiter() -> Box<dyn Waiter + Send> {
+    log::warn!("Event-driven process detection is only available on Linux, falling back to polling.");
+    Box::new(PollWaiter)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::collections::{HashMap, HashSet};
+    use std::io;
+    use std::mem;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::Waiter;
+    use crate::session::ProcessExit;
+
+    const CN_IDX_PROC: u32 = 1;
+    const CN_VAL_PROC: u32 = 1;
+    const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+    const PROC_EVENT_NONE: u32 = 0x0;
+    const PROC_EVENT_EXEC: u32 = 0x2;
+    const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+    const NLMSG_HDRLEN: usize = 16;
+    const CN_MSG_LEN: usize = 20;
+    /// `what`, `cpu` and `timestamp_ns` precede the event data in `struct proc_event`.
+    const PROC_EVENT_HEADER_LEN: usize = 16;
+
+    /// How long to wait for the kernel to acknowledge our subscription.
+    const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+    /// Pause after an exec so a burst of launches is handled by a single scan.
+    const EXEC_SETTLE: Duration = Duration::from_millis(100);
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum ProcEvent {
+        Ack { err: u32 },
+        Exec,
+        /// `exit_code` is a wait status, see `ProcessExit::from_wait_status`.
+        Exit { pid: u32, tgid: u32, exit_code: u32 },
+    }
+
+    /// Subscription to the kernel's process events over the netlink proc connector.
+    /// Needs CAP_NET_ADMIN, so unprivileged services will fail to open it.
+    struct ProcConnector {
+        fd: OwnedFd,
+    }
+
+    impl ProcConnector {
+        fn open() -> io::Result<Self> {
+            let raw = unsafe {
+                libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_DGRAM | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                    libc::NETLINK_CONNECTOR,
+                )
+            };
+            if raw < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+            let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            addr.nl_groups = CN_IDX_PROC;
+            let rc = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if rc < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let connector = Self { fd };
+            connector.subscribe()?;
+            Ok(connector)
+        }
+
+        fn raw_fd(&self) -> RawFd {
+            self.fd.as_raw_fd()
+        }
+
+        /// Sends PROC_CN_MCAST_LISTEN and waits for the kernel's verdict.
+        fn subscribe(&self) -> io::Result<()> {
+            let total = NLMSG_HDRLEN + CN_MSG_LEN + 4;
+            let mut msg = Vec::with_capacity(total);
+            // struct nlmsghdr
+            msg.extend_from_slice(&(total as u32).to_ne_bytes());
+            msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+            msg.extend_from_slice(&0u16.to_ne_bytes());
+            msg.extend_from_slice(&0u32.to_ne_bytes());
+            msg.extend_from_slice(&std::process::id().to_ne_bytes());
+            // struct cn_msg
+            msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+            msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+            msg.extend_from_slice(&0u32.to_ne_bytes());
+            msg.extend_from_slice(&0u32.to_ne_bytes());
+            msg.extend_from_slice(&4u16.to_ne_bytes());
+            msg.extend_from_slice(&0u16.to_ne_bytes());
+            // enum proc_cn_mcast_op
+            msg.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+
+            let sent = unsafe { libc::send(self.raw_fd(), msg.as_ptr().cast(), msg.len(), 0) };
+            if sent < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let deadline = Instant::now() + ACK_TIMEOUT;
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                if !poll_readable(self.raw_fd(), remaining)? {
+                    break;
+                }
+
+                let mut result = None;
+                self.read_events(|event| {
+                    if let ProcEvent::Ack { err } = event {
+                        result = Some(err);
+                    }
+                })?;
+
+                match result {
+                    Some(0) => return Ok(()),
+                    Some(err) => return Err(io::Error::from_raw_os_error(err as i32)),
+                    None => continue,
+                }
+            }
+
+            Err(io::Error::new(io::ErrorKind::TimedOut, "no acknowledgement from the proc connector"))
+        }
+
+        /// Reads every queued message, passing each process event to `f`.
+        fn read_events(&self, mut f: impl FnMut(ProcEvent)) -> io::Result<()> {
+            let mut buf = [0u8; 4096];
+            loop {
+                let len = unsafe { libc::recv(self.raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+                if len < 0 {
+                    let err = io::Error::last_os_error();
+                    return match err.kind() {
+                        io::ErrorKind::WouldBlock => Ok(()),
+                        io::ErrorKind::Interrupted => continue,
+                        _ => Err(err),
+                    };
+                }
+                parse_messages(&buf[..len as usize], &mut f);
+            }
+        }
+    }
+
+    fn parse_messages(buf: &[u8], f: &mut impl FnMut(ProcEvent)) {
+        let mut offset = 0;
+        while offset + NLMSG_HDRLEN <= buf.len() {
+            let len = read_u32(buf, offset) as usize;
+            if len < NLMSG_HDRLEN || offset + len > buf.len() {
+                break;
+            }
+            if let Some(event) = parse_event(&buf[offset + NLMSG_HDRLEN..offset + len]) {
+                f(event);
+            }
+            // NLMSG_ALIGN
+            offset += (len + 3) & !3;
+        }
+    }
+
+    fn parse_event(payload: &[u8]) -> Option<ProcEvent> {
+        if payload.len() < CN_MSG_LEN + PROC_EVENT_HEADER_LEN || read_u32(payload, 0) != CN_IDX_PROC {
+            return None;
+        }
+
+        let event = &payload[CN_MSG_LEN..];
+        let data = PROC_EVENT_HEADER_LEN;
+        match read_u32(event, 0) {
+            PROC_EVENT_NONE if event.len() >= data + 4 => Some(ProcEvent::Ack { err: read_u32(event, data) }),
+            PROC_EVENT_EXEC => Some(ProcEvent::Exec),
+            PROC_EVENT_EXIT if event.len() >= data + 12 => Some(ProcEvent::Exit {
+                pid: read_u32(event, data),
+                tgid: read_u32(event, data + 4),
+                exit_code: read_u32(event, data + 8),
+            }),
+            _ => None,
+        }
+    }
+
+    fn read_u32(buf: &[u8], offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(&buf[offset..offset + 4]);
+        u32::from_ne_bytes(bytes)
+    }
+
+    /// Opens a pidfd, which becomes readable once the process exits.
+    fn pidfd_open(pid: u32) -> io::Result<OwnedFd> {
+        let raw = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(unsafe { OwnedFd::from_raw_fd(raw as RawFd) })
+    }
+
+    fn poll_readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+        let mut fds = [libc::pollfd { fd, events: libc::POLLIN, revents: 0 }];
+        let rc = unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms(timeout)) };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(rc > 0)
+    }
+
+    fn timeout_ms(timeout: Duration) -> libc::c_int {
+        timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int
+    }
+
+    /// Wakes on process execs through the proc connector and on session exits
+    /// through pidfds. Either source may be missing; without the connector new
+    /// games are still only found when the timeout expires.
+    pub struct EventWaiter {
+        connector: Option<ProcConnector>,
+        pidfds: HashMap<u32, OwnedFd>,
+        /// Tracked PIDs whose pidfd has already woken a wait. An exited
+        /// process stays readable until it is reaped, so its pidfd is closed
+        /// rather than polled again while the PID is still tracked.
+        fired: HashSet<u32>,
+        /// Exits of tracked processes not yet collected by `take_exits`.
+        exits: HashMap<u32, ProcessExit>,
+    }
+
+    impl EventWaiter {
+        pub fn new() -> Self {
+            let connector = ProcConnector::open()
+                .map_err(|e| {
+                    log::warn!("Proc connector unavailable ({}), falling back to polling for new processes.", e)
+                })
+                .ok();
+
+            Self { connector, pidfds: HashMap::new(), fired: HashSet::new(), exits: HashMap::new() }
+        }
+
+        /// Keeps exactly one pidfd per tracked PID that hasn't exited yet.
+        fn sync_pidfds(&mut self, tracked: &[u32]) {
+            self.pidfds.retain(|pid, _| tracked.contains(pid));
+            self.fired.retain(|pid| tracked.contains(pid));
+            for pid in tracked {
+                if self.pidfds.contains_key(pid) || self.fired.contains(pid) {
+                    continue;
+                }
+                match pidfd_open(*pid) {
+                    Ok(fd) => {
+                        self.pidfds.insert(*pid, fd);
+                    }
+                    Err(e) => log::debug!("pidfd_open({}) failed: {}", pid, e),
+                }
+            }
+        }
+
+        /// Drains the connector. Returns true if a new program started or a
+        /// tracked process exited, or if events may have been lost.
+        fn drain_connector(&mut self, tracked: &[u32]) -> bool {
+            let Some(connector) = &self.connector else {
+                return false;
+            };
+
+            let mut relevant = false;
+            let exits = &mut self.exits;
+            let result = connector.read_events(|event| match event {
+                ProcEvent::Exec => relevant = true,
+                ProcEvent::Exit { pid, tgid, exit_code } if pid == tgid && tracked.contains(&tgid) => {
+                    exits.insert(tgid, ProcessExit::from_wait_status(exit_code));
+                    relevant = true;
+                }
+                _ => {}
+            });
+
+            if let Err(e) = result {
+                // ENOBUFS means the kernel dropped events; rescan to catch up
+                log::debug!("Proc connector read failed: {}", e);
+                return true;
+            }
+            relevant
+        }
+    }
+
+    impl Waiter for EventWaiter {
+        fn wait(&mut self, tracked: &[u32], timeout: Duration) {
+            self.sync_pidfds(tracked);
+
+            let pids: Vec<u32> = self.pidfds.keys().copied().collect();
+            let mut fds: Vec<libc::pollfd> = self
+                .connector
+                .iter()
+                .map(ProcConnector::raw_fd)
+                .chain(pids.iter().map(|pid| self.pidfds[pid].as_raw_fd()))
+                .map(|fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 })
+                .collect();
+            let pidfd_start = usize::from(self.connector.is_some());
+
+            let deadline = Instant::now() + timeout;
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                if fds.is_empty() {
+                    thread::sleep(remaining);
+                    return;
+                }
+
+                let rc = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms(remaining)) };
+                if rc < 0 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    log::warn!("poll failed: {}", err);
+                    thread::sleep(remaining);
+                    return;
+                }
+                if rc == 0 {
+                    return;
+                }
+
+                let exited: Vec<u32> = pids
+                    .iter()
+                    .zip(&fds[pidfd_start..])
+                    .filter(|(_, pollfd)| pollfd.revents != 0)
+                    .map(|(pid, _)| *pid)
+                    .collect();
+                if !exited.is_empty() {
+                    for pid in exited {
+                        self.pidfds.remove(&pid);
+                        self.fired.insert(pid);
+                    }
+                    // The kernel queues the exit event before the pidfd wakes
+                    self.drain_connector(tracked);
+                    return;
+                }
+
+                if self.drain_connector(tracked) {
+                    thread::sleep(EXEC_SETTLE);
+                    self.drain_connector(tracked);
+                    return;
+                }
+
+                for pollfd in fds.iter_mut() {
+                    pollfd.revents = 0;
+                }
+            }
+        }
+
+        fn is_event_driven(&self) -> bool {
+            self.connector.is_some()
+        }
+
+        fn take_exits(&mut self) -> HashMap<u32, ProcessExit> {
+            mem::take(&mut self.exits)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// A netlink message from the proc connector carrying event `what`
+        /// with `data` after the event header.
+        fn message(what: u32, data: &[u32]) -> Vec<u8> {
+            let len = NLMSG_HDRLEN + CN_MSG_LEN + PROC_EVENT_HEADER_LEN + data.len() * 4;
+            let mut msg = Vec::new();
+            // struct nlmsghdr
+            msg.extend_from_slice(&(len as u32).to_ne_bytes());
+            msg.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+            msg.extend_from_slice(&[0; 10]);
+            // struct cn_msg
+            msg.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+            msg.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+            msg.extend_from_slice(&[0; 8]);
+            msg.extend_from_slice(&((PROC_EVENT_HEADER_LEN + data.len() * 4) as u16).to_ne_bytes());
+            msg.extend_from_slice(&[0; 2]);
+            // struct proc_event: what, cpu, timestamp_ns
+            msg.extend_from_slice(&what.to_ne_bytes());
+            msg.extend_from_slice(&[0; 12]);
+            for value in data {
+                msg.extend_from_slice(&value.to_ne_bytes());
+            }
+            msg
+        }
+
+        fn events(buf: &[u8]) -> Vec<ProcEvent> {
+            let mut events = Vec::new();
+            parse_messages(buf, &mut |event| events.push(event));
+            events
+        }
+
+        #[test]
+        fn exec_and_exit_events_are_parsed() {
+            // exec: pid, tgid
+            assert_eq!(events(&message(PROC_EVENT_EXEC, &[100, 100])), [ProcEvent::Exec]);
+            // exit: pid, tgid, exit_code, exit_signal
+            assert_eq!(
+                events(&message(PROC_EVENT_EXIT, &[101, 100, 11, 17])),
+                [ProcEvent::Exit { pid: 101, tgid: 100, exit_code: 11 }]
+            );
+            assert_eq!(events(&message(PROC_EVENT_NONE, &[0])), [ProcEvent::Ack { err: 0 }]);
+        }
+
+        #[test]
+        fn several_messages_in_one_read_are_all_parsed() {
+            let mut buf = message(PROC_EVENT_EXEC, &[100, 100]);
+            buf.extend(message(PROC_EVENT_EXIT, &[100, 100, 1 << 8, 17]));
+            assert_eq!(
+                events(&buf),
+                [ProcEvent::Exec, ProcEvent::Exit { pid: 100, tgid: 100, exit_code: 1 << 8 }]
+            );
+        }
+
+        #[test]
+        fn short_foreign_and_unknown_messages_are_skipped() {
+            // fork events aren't used
+            assert_eq!(events(&message(0x1, &[1, 1, 100, 100])), []);
+            // An exit event cut off before its exit code
+            assert_eq!(events(&message(PROC_EVENT_EXIT, &[100, 100])), []);
+
+            let mut foreign = message(PROC_EVENT_EXEC, &[100, 100]);
+            foreign[NLMSG_HDRLEN..NLMSG_HDRLEN + 4].copy_from_slice(&7u32.to_ne_bytes());
+            assert_eq!(events(&foreign), []);
+
+            // A length running past the end of the buffer
+            let whole = message(PROC_EVENT_EXEC, &[100, 100]);
+            assert_eq!(events(&whole[..whole.len() - 4]), []);
+        }
+
+        #[test]
+        fn an_unreaped_exit_wakes_only_one_wait() {
+            let mut child = std::process::Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+            let pid = child.id();
+            let mut waiter =
+                EventWaiter { connector: None, pidfds: HashMap::new(), fired: HashSet::new(), exits: HashMap::new() };
+
+            let started = Instant::now();
+            waiter.wait(&[pid], Duration::from_secs(5));
+            assert!(started.elapsed() < Duration::from_secs(4));
+
+            // Still a zombie, but its pidfd has had its say
+            let started = Instant::now();
+            waiter.wait(&[pid], Duration::from_millis(300));
+            assert!(started.elapsed() >= Duration::from_millis(300));
+
+            child.wait().unwrap();
+        }
+    }
+}
@@ -0,0 +1,483 @@
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use crate::config::{CommandSpec, RunMode};
+
+/// How long a timed-out command gets between SIGTERM and SIGKILL.
+const KILL_GRACE: Duration = Duration::from_secs(5);
+
+/// How often a command with a time limit is checked on.
+const WAIT_STEP: Duration = Duration::from_millis(50);
+
+/// How long to wait for output after a command exits.
+const OUTPUT_WAIT: Duration = Duration::from_secs(1);
+
+/// How much of each output stream a `CommandReport` keeps.
+pub const REPORT_OUTPUT_LIMIT: usize = 4096;
+
+/// Builds the process for `spec` with `env` added to its environment.
+/// Shell commands go through `sh -c`; program commands run directly unless
+/// they ask for a shell.
+pub fn command(spec: &CommandSpec, env: &[(String, String)]) -> Command {
+    match spec {
+        CommandSpec::Shell(cmd) => {
+            let mut command = shell(cmd);
+            command.envs(env.iter().map(|(k, v)| (k, v)));
+            command
+        }
+        CommandSpec::Program(program) => {
+            let mut command = if program.shell {
+                // The first argument after the script is `$0`
+                let mut command = shell(&program.program);
+                command.arg("sh").args(&program.args);
+                command
+            } else {
+                let mut command = Command::new(expand_home(&program.program));
+                command.args(&program.args);
+                command
+            };
+
+            if let Some(cwd) = &program.cwd {
+                command.current_dir(expand_home(cwd));
+            }
+            command.envs(env.iter().map(|(k, v)| (k, v)));
+            command.envs(&program.env);
+            command
+        }
+    }
+}
+
+/// How a command run by `run` finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    /// Ran past its time limit and was stopped.
+    TimedOut(Duration),
+}
+
+/// What happened to one command, for the log and the GUI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandReport {
+    pub command: String,
+    pub outcome: Outcome,
+    /// `None` if the command was stopped by a signal or never started.
+    pub exit_code: Option<i32>,
+    pub duration: Duration,
+    /// What it printed, cut to `REPORT_OUTPUT_LIMIT` bytes each.
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandReport {
+    fn new(spec: &CommandSpec, outcome: Outcome, started: Instant) -> Self {
+        Self {
+            command: spec.to_string(),
+            outcome,
+            exit_code: None,
+            duration: started.elapsed(),
+            stdout: String::new(),
+            stderr: String::new(),
+        }
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.outcome == Outcome::Succeeded
+    }
+}
+
+impl std::fmt::Display for CommandReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match (self.outcome, self.exit_code) {
+            (Outcome::TimedOut(limit), _) => format!("timed out after {}s", limit.as_secs()),
+            (_, Some(code)) => format!("exit {}", code),
+            (Outcome::Succeeded, None) => "ok".to_string(),
+            (Outcome::Failed, None) => "failed".to_string(),
+        };
+        write!(f, "[{}, {:.2}s] {}", status, self.duration.as_secs_f64(), self.command)
+    }
+}
+
+/// Runs `commands` one after another, or all at once in `RunMode::Parallel`,
+/// and reports on each one that ran. With `stop_on_failure`, a sequential
+/// run stops at the first command that doesn't succeed.
+pub fn run_all(
+    commands: &[CommandSpec],
+    env: &[(String, String)],
+    timeout: Option<Duration>,
+    mode: RunMode,
+    stop_on_failure: bool,
+) -> Vec<CommandReport> {
+    match mode {
+        RunMode::Sequential => {
+            let mut reports = Vec::new();
+            for spec in commands {
+                let report = run(spec, env, timeout);
+                let failed = !report.succeeded();
+                reports.push(report);
+                if failed && stop_on_failure {
+                    log::warn!("Stopping after a failed command, {} left unrun.", commands.len() - reports.len());
+                    break;
+                }
+            }
+            reports
+        }
+        RunMode::Parallel => thread::scope(|scope| {
+            let handles: Vec<_> = commands
+                .iter()
+                .map(|spec| scope.spawn(move || run(spec, env, timeout)))
+                .collect();
+            handles
+                .into_iter()
+                .zip(commands)
+                .map(|(handle, spec)| {
+                    handle.join().unwrap_or_else(|_| CommandReport::new(spec, Outcome::Failed, Instant::now()))
+                })
+                .collect()
+        }),
+    }
+}
+
+/// Runs `spec` to completion, logging its output. `timeout` applies unless
+/// the command sets its own; when it runs out, the command's process group
+/// gets SIGTERM and, `KILL_GRACE` later, SIGKILL.
+pub fn run(spec: &CommandSpec, env: &[(String, String)], timeout: Option<Duration>) -> CommandReport {
+    let started = Instant::now();
+    if spec.is_blank() {
+        log::warn!("⚠️ Empty command string provided, skipping execution.");
+        return CommandReport::new(spec, Outcome::Failed, started);
+    }
+
+    log::info!("🟢 Running command: {}", spec);
+
+    #[cfg(windows)]
+    if let CommandSpec::Shell(cmd) = spec {
+        return match crate::config::run_windows_cmd(cmd) {
+            Ok(_) => {
+                log::info!("{:?} executed successfully", cmd);
+                CommandReport::new(spec, Outcome::Succeeded, started)
+            }
+            Err(e) => {
+                log::error!("Failed to execute command '{}': {}", cmd, e);
+                CommandReport { stderr: e.to_string(), ..CommandReport::new(spec, Outcome::Failed, started) }
+            }
+        };
+    }
+
+    let mut command = command(spec, env);
+    command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    // Its own group, so a timeout also stops whatever the command started
+    #[cfg(unix)]
+    command.process_group(0);
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("❌ Failed to execute command '{}': {}", spec, e);
+            return CommandReport { stderr: e.to_string(), ..CommandReport::new(spec, Outcome::Failed, started) };
+        }
+    };
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let limit = spec.timeout(timeout);
+    let status = match limit {
+        Some(limit) => match wait_timeout(&mut child, limit) {
+            Ok(Some(status)) => Ok(status),
+            Ok(None) => {
+                log::warn!("⏱️ Command '{}' ran longer than {}s, stopping it...", spec, limit.as_secs());
+                terminate(&mut child);
+                let report = CommandReport::new(spec, Outcome::TimedOut(limit), started);
+                return with_output(report, stdout, stderr);
+            }
+            Err(e) => Err(e),
+        },
+        None => child.wait(),
+    };
+
+    let report = match status {
+        Ok(status) if status.success() => {
+            log::info!("✅ Command executed successfully.");
+            CommandReport { exit_code: status.code(), ..CommandReport::new(spec, Outcome::Succeeded, started) }
+        }
+        Ok(status) => {
+            log::error!("❌ Command exited with status: {}", status);
+            CommandReport { exit_code: status.code(), ..CommandReport::new(spec, Outcome::Failed, started) }
+        }
+        Err(e) => {
+            log::error!("❌ Failed to wait for command '{}': {}", spec, e);
+            CommandReport::new(spec, Outcome::Failed, started)
+        }
+    };
+    with_output(report, stdout, stderr)
+}
+
+/// Waits up to `limit` for `child` to exit. `None` means it is still running.
+fn wait_timeout(child: &mut Child, limit: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + limit;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(WAIT_STEP.min(deadline - now));
+    }
+}
+
+/// Starts `spec` in its own process group without waiting for it. Its
+/// output is discarded, since it may run for hours.
+pub fn spawn(spec: &CommandSpec, env: &[(String, String)]) -> io::Result<Child> {
+    let mut command = command(spec, env);
+    command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    #[cfg(unix)]
+    command.process_group(0);
+    command.spawn()
+}
+
+/// Asks the process group of `child`, started by `run` or `spawn`, to exit,
+/// then kills it if it hasn't within `KILL_GRACE`.
+#[cfg(unix)]
+pub fn terminate(child: &mut Child) {
+    let group = child.id() as libc::pid_t;
+
+    // SAFETY: killpg only sends a signal; the group was created for this command
+    unsafe { libc::killpg(group, libc::SIGTERM) };
+    if let Ok(Some(_)) = wait_timeout(child, KILL_GRACE) {
+        return;
+    }
+
+    log::warn!("Command did not exit {}s after SIGTERM, sending SIGKILL.", KILL_GRACE.as_secs());
+    // SAFETY: as above
+    unsafe { libc::killpg(group, libc::SIGKILL) };
+    let _ = child.wait();
+}
+
+#[cfg(windows)]
+pub fn terminate(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Collects a pipe on its own thread so a chatty command can't fill it and stall.
+fn read_in_background<R: Read + Send + 'static>(mut pipe: R) -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut data = Vec::new();
+        let _ = pipe.read_to_end(&mut data);
+        let _ = tx.send(data);
+    });
+    rx
+}
+
+/// Logs what the command printed and adds it to `report`. A process it left
+/// running in the background may still hold the pipes open, so this doesn't
+/// wait for long.
+fn with_output(
+    report: CommandReport,
+    stdout: Option<mpsc::Receiver<Vec<u8>>>,
+    stderr: Option<mpsc::Receiver<Vec<u8>>>,
+) -> CommandReport {
+    let deadline = Instant::now() + OUTPUT_WAIT;
+    let collect = |rx: Option<mpsc::Receiver<Vec<u8>>>| {
+        rx.and_then(|rx| rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok())
+            .unwrap_or_default()
+    };
+
+    let stdout = collect(stdout);
+    if !stdout.is_empty() {
+        log::info!("✅ STDOUT:\n{}", String::from_utf8_lossy(&stdout));
+    }
+    let stderr = collect(stderr);
+    if !stderr.is_empty() {
+        log::error!("⚠️ STDERR:\n{}", String::from_utf8_lossy(&stderr));
+    }
+
+    CommandReport { stdout: truncated(&stdout), stderr: truncated(&stderr), ..report }
+}
+
+/// `output` as text, cut to `REPORT_OUTPUT_LIMIT` bytes.
+fn truncated(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    if text.len() <= REPORT_OUTPUT_LIMIT {
+        return text.into_owned();
+    }
+
+    let mut end = REPORT_OUTPUT_LIMIT;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n… ({} more bytes)", &text[..end], text.len() - end)
+}
+
+#[cfg(unix)]
+fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd);
+    command
+}
+
+#[cfg(windows)]
+fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(cmd);
+    command
+}
+
+/// Replaces a leading `~` with the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::config::ProgramCommand;
+
+    /// Processes in group `group` that are still running. Zombies don't count:
+    /// orphans linger as those until init gets round to reaping them.
+    fn live_members(group: i32) -> Vec<u32> {
+        let Ok(dir) = std::fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        dir.flatten()
+            .filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+            .filter(|pid| {
+                let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+                    return false;
+                };
+                // Fields after comm: state, ppid, pgrp, ...
+                let fields: Vec<&str> = stat.rsplit_once(')').map_or("", |(_, rest)| rest).split_whitespace().collect();
+                fields.get(2).and_then(|pgrp| pgrp.parse::<i32>().ok()) == Some(group)
+                    && !matches!(fields.first(), Some(&"Z") | Some(&"X"))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sleep_past_the_limit_times_out() {
+        let started = Instant::now();
+        let report = run(&CommandSpec::from("sleep 5"), &[], Some(Duration::from_secs(1)));
+
+        assert_eq!(report.outcome, Outcome::TimedOut(Duration::from_secs(1)));
+        assert_eq!(report.exit_code, None);
+        assert!(!report.succeeded());
+        assert!(started.elapsed() < Duration::from_secs(4), "took {:?}", started.elapsed());
+    }
+
+    #[test]
+    fn timeout_stops_the_whole_process_group() {
+        // The shell leads the group and prints its ID; the background sleep is in it too
+        let spec = CommandSpec::from("echo $$; sleep 30 & sleep 30");
+        let report = run(&spec, &[], Some(Duration::from_secs(1)));
+
+        assert!(matches!(report.outcome, Outcome::TimedOut(_)));
+        let group: i32 = report.stdout.trim().parse().unwrap();
+        let left = live_members(group);
+        assert!(left.is_empty(), "process group {} still has {:?} running", group, left);
+    }
+
+    #[test]
+    fn timeout_seconds_zero_lifts_the_entry_limit() {
+        let spec = CommandSpec::from_line(r#"{ program = "sleep", args = ["2"], timeout_seconds = 0 }"#);
+        let report = run(&spec, &[], Some(Duration::from_secs(1)));
+        assert_eq!(report.outcome, Outcome::Succeeded);
+        assert!(report.duration >= Duration::from_secs(2));
+
+        // A command's own limit beats a longer one for the entry
+        let spec = CommandSpec::from_line(r#"{ program = "sleep", args = ["5"], timeout_seconds = 1 }"#);
+        let report = run(&spec, &[], Some(Duration::from_secs(60)));
+        assert_eq!(report.outcome, Outcome::TimedOut(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn commands_within_the_limit_report_their_exit() {
+        let report = run(&CommandSpec::from("echo out; echo err >&2; exit 3"), &[], Some(Duration::from_secs(5)));
+        assert_eq!(report.outcome, Outcome::Failed);
+        assert_eq!(report.exit_code, Some(3));
+        assert_eq!((report.stdout.as_str(), report.stderr.as_str()), ("out\n", "err\n"));
+    }
+
+    fn outcomes(reports: &[CommandReport]) -> Vec<Outcome> {
+        reports.iter().map(|report| report.outcome).collect()
+    }
+
+    #[test]
+    fn sequential_runs_stop_at_the_first_failure_only_when_asked() {
+        let commands = ["true", "exit 1", "true"].map(CommandSpec::from);
+
+        let reports = run_all(&commands, &[], None, RunMode::Sequential, true);
+        assert_eq!(outcomes(&reports), [Outcome::Succeeded, Outcome::Failed]);
+
+        let reports = run_all(&commands, &[], None, RunMode::Sequential, false);
+        assert_eq!(outcomes(&reports), [Outcome::Succeeded, Outcome::Failed, Outcome::Succeeded]);
+    }
+
+    #[test]
+    fn parallel_runs_start_every_command_at_once() {
+        let commands = ["sleep 1; exit 1", "sleep 1", "sleep 1"].map(CommandSpec::from);
+
+        let started = Instant::now();
+        let reports = run_all(&commands, &[], None, RunMode::Parallel, true);
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+        // Nothing is left to stop once one fails, and reports keep the list's order
+        assert_eq!(outcomes(&reports), [Outcome::Failed, Outcome::Succeeded, Outcome::Succeeded]);
+    }
+
+    fn output(spec: &CommandSpec, env: &[(String, String)]) -> String {
+        let report = run(spec, env, Some(Duration::from_secs(5)));
+        assert_eq!(report.outcome, Outcome::Succeeded, "{}", report.stderr);
+        report.stdout
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn programs_run_in_their_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let cwd = dir.path().canonicalize().unwrap();
+        let spec = CommandSpec::Program(ProgramCommand {
+            program: "pwd".to_string(),
+            cwd: Some(cwd.display().to_string()),
+            ..Default::default()
+        });
+        assert_eq!(output(&spec, &[]), format!("{}\n", cwd.display()));
+    }
+
+    #[test]
+    fn program_env_is_added_over_the_session_env() {
+        let spec = CommandSpec::from_line(r#"{ program = "printenv", args = ["GAMEMON_GAME", "SINK"], env = { SINK = "headset" } }"#);
+        let env = vars(&[("GAMEMON_GAME", "Elden Ring"), ("SINK", "speakers")]);
+        assert_eq!(output(&spec, &env), "Elden Ring\nheadset\n");
+    }
+
+    #[test]
+    fn program_args_reach_the_child_without_a_shell() {
+        let spec = CommandSpec::from_line(r#"{ program = "printf", args = ["%s|", "$HOME", "a b", "*", "'quoted'"] }"#);
+        assert_eq!(output(&spec, &[]), "$HOME|a b|*|'quoted'|");
+    }
+
+    #[test]
+    fn shell_commands_go_through_sh() {
+        let env = vars(&[("GAMEMON_GAME", "Elden Ring")]);
+        assert_eq!(output(&CommandSpec::from("echo \"$GAMEMON_GAME\" | tr a-z A-Z"), &env), "ELDEN RING\n");
+
+        // With `shell = true` the args are the script's positional parameters
+        let spec = CommandSpec::from_line(r#"{ program = "echo \"$1-$2\" $0", args = ["a b", "c"], shell = true }"#);
+        assert_eq!(output(&spec, &[]), "a b-c sh\n");
+    }
+}
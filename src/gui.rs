@@ -1,7 +1,7 @@
 use game_mon::app::Gui;
 use iced::settings::Settings;
 use iced::window::settings::Settings as Win_Settings;
-use game_mon::config::{GAMEMON_LOGO, check_for_updates, CURRENT_VERSION};
+use game_mon::config::{Config, GAMEMON_CONFIG_FILE, GAMEMON_LOGO, check_for_updates, CURRENT_VERSION};
 mod logger;
 
 
@@ -9,9 +9,16 @@ pub fn main() -> iced::Result {
 
     logger::Logger::init_with_target("GameMon-service").expect("Failed to initialize logger");
 
-    match check_for_updates("".to_string()) {
-        Ok(_) => log::info!("Check for updates complete!"),
-        Err(e) => log::error!("Error checking for updates: {:?}\n", e),
+    let settings = Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy())
+        .map(|config| config.settings)
+        .unwrap_or_default();
+    log::set_max_level(settings.log_level.filter());
+
+    if settings.updates.auto_check {
+        match check_for_updates("".to_string()) {
+            Ok(_) => log::info!("Check for updates complete!"),
+            Err(e) => log::error!("Error checking for updates: {:?}\n", e),
+        }
     }
 
     // Start the GUI application
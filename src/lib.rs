@@ -3,9 +3,21 @@
 
 // Declare the modules for the crate
 pub mod app;
+pub mod bundle;
+pub mod companion;
 pub mod config;
+pub mod events;
+pub mod executor;
+pub mod matcher;
+pub mod migration;
+pub mod notification;
+pub mod process;
 pub mod tray;
 pub mod service;
+pub mod session;
+pub mod steam;
+pub mod validate;
+pub mod wine;
 
 // Optionally, re-export commonly used items for convenience
 // pub use mods::app;
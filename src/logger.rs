@@ -138,7 +138,7 @@ impl Logger {
 
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= Level::Info // Adjust as needed
+        metadata.level() <= log::max_level() // Follows the configured log level
     }
 
     fn log(&self, record: &Record) {
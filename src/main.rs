@@ -1,7 +1,7 @@
 use std::process::{exit, Command};
 use std::sync::mpsc;
-use std::{env, fs, thread, path::Path};
-use std::time::Duration;
+use std::{env, fs, thread, path::{Path, PathBuf}};
+use std::time::{Duration, SystemTime};
 use game_mon::config::{check_for_updates,
     Config,
     GAMEMON_BIN_DIR,
@@ -10,8 +10,11 @@ use game_mon::config::{check_for_updates,
     GAMEMON_GUI_EXECUTABLE,
     GAMEMON_RESOURCE_DIR
 };
-use game_mon::service;
+use game_mon::bundle;
+use game_mon::migration;
+use game_mon::service::{self, CommandContext, CommandOptions};
 use game_mon::tray;
+use game_mon::validate;
 
 mod logger;
 
@@ -20,7 +23,7 @@ use std::sync::Arc;
 use signal_hook::consts::signal::*;
 use signal_hook::iterator::Signals;
 use gtk::glib;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(name = "GameMon-service")]
@@ -28,6 +31,60 @@ use clap::Parser;
 struct Args {
     #[arg(long)]
     install_resources: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Check a config file and its profiles.d, print any problems; exits non-zero on errors
+    Validate {
+        /// Defaults to the user's config.toml
+        path: Option<PathBuf>,
+    },
+    /// Write one or more profiles to a bundle file (.toml, or .json)
+    Export {
+        /// Game names of the profiles to export
+        #[arg(required = true)]
+        games: Vec<String>,
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, default_value = "")]
+        author: String,
+        #[arg(long, default_value = "")]
+        description: String,
+        /// Operating system the profiles are for, e.g. linux (repeatable)
+        #[arg(long = "os")]
+        target_os: Vec<String>,
+        /// Program the commands need on PATH (repeatable)
+        #[arg(long)]
+        requires: Vec<String>,
+    },
+    /// Add the profiles from a bundle file to config.toml
+    Import {
+        path: PathBuf,
+        /// What to do with profiles whose name is already taken
+        #[arg(long, value_enum)]
+        on_collision: Option<OnCollision>,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum OnCollision {
+    Rename,
+    Merge,
+    Skip,
+}
+
+impl From<OnCollision> for bundle::Resolution {
+    fn from(value: OnCollision) -> Self {
+        match value {
+            OnCollision::Rename => bundle::Resolution::Rename,
+            OnCollision::Merge => bundle::Resolution::Merge,
+            OnCollision::Skip => bundle::Resolution::Skip,
+        }
+    }
 }
 
 #[cfg(windows)]
@@ -49,6 +106,21 @@ pub fn main() {
         return;
     }
 
+    match args.command {
+        Some(Commands::Validate { path }) => {
+            let path = path.unwrap_or_else(|| GAMEMON_CONFIG_FILE.clone());
+            exit(validate_config(&path));
+        }
+        Some(Commands::Export { games, output, author, description, target_os, requires }) => {
+            let meta = bundle::BundleMeta { author, description, target_os, requires, ..Default::default() };
+            exit(export_bundle(&games, meta, &output));
+        }
+        Some(Commands::Import { path, on_collision }) => {
+            exit(import_bundle(&path, on_collision.map(Into::into)));
+        }
+        None => {}
+    }
+
     logger::Logger::init().expect("Failed to initialize logger");
     log::info!("MAIN FUNCTION ENTRY: Starting GameMon...");
 
@@ -112,6 +184,13 @@ pub fn main() {
         env::set_var("PATH", path);
     }
 
+    // Upgrade an outdated config.toml once, before anything else reads it
+    match migration::migrate_file(GAMEMON_CONFIG_FILE.as_path()) {
+        Ok(Some(backup)) => log::info!("Config migrated, original saved to {:?}", backup),
+        Ok(None) => {}
+        Err(e) => log::error!("Failed to migrate config: {}", e),
+    }
+
     let (wtx, wrx) = mpsc::channel(); // watchdog
     let (ttx, trx) = mpsc::channel(); // tray
 
@@ -172,7 +251,13 @@ pub fn main() {
                     log::info!("Running start commands for {}", game_name);
                     if let Ok(config) = Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
                         if let Some(entry) = config.entries.iter().find(|e| e.game_name == game_name) {
-                            let _ = service::run_commands(&entry.start_commands);
+                            match config.resolve_entry(entry) {
+                                Ok(entry) => {
+                                    let options = CommandOptions::new(&config, Some(&entry));
+                                    service::run_commands(&entry.start_commands, &CommandContext::for_entry(&entry, SystemTime::now()), options);
+                                }
+                                Err(e) => log::error!("Cannot run start commands for {}: {}", game_name, e),
+                            }
                         }
                     }
                 }
@@ -181,7 +266,13 @@ pub fn main() {
                     log::info!("Running end commands for {}", game_name);
                     if let Ok(config) = Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
                         if let Some(entry) = config.entries.iter().find(|e| e.game_name == game_name) {
-                            let _ = service::run_commands(&entry.end_commands);
+                            match config.resolve_entry(entry) {
+                                Ok(entry) => {
+                                    let options = CommandOptions::new(&config, Some(&entry));
+                                    service::run_commands(&entry.end_commands, &CommandContext::for_entry(&entry, SystemTime::now()), options);
+                                }
+                                Err(e) => log::error!("Cannot run end commands for {}: {}", game_name, e),
+                            }
                         }
                     }
                 }
@@ -199,9 +290,126 @@ pub fn main() {
         thread::sleep(Duration::from_secs(1));
     }
 
+    match Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
+        Ok(config) => {
+            let options = CommandOptions::new(&config, None);
+            service::run_hooks("on_service_stop", &config.hooks.on_service_stop, &CommandContext::global(), options);
+        }
+        Err(e) => log::error!("Could not load config for on_service_stop hooks: {}", e),
+    }
+
     log::info!("Main function exiting.");
 }
 
+/// Prints the diagnostics for `path` and returns the process exit code.
+fn validate_config(path: &Path) -> i32 {
+    let diagnostics = match validate::validate_file(path) {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return 2;
+        }
+    };
+
+    // Diagnostics from profiles.d already name their file
+    for diagnostic in &diagnostics {
+        match diagnostic.file {
+            Some(_) => println!("{}", diagnostic),
+            None => println!("{}: {}", path.display(), diagnostic),
+        }
+    }
+
+    if validate::has_errors(&diagnostics) {
+        1
+    } else {
+        if diagnostics.is_empty() {
+            println!("{}: OK", path.display());
+        }
+        0
+    }
+}
+
+fn export_bundle(games: &[String], meta: bundle::BundleMeta, output: &Path) -> i32 {
+    let config = match Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            return 1;
+        }
+    };
+
+    match bundle::export(&config, games, meta, output) {
+        Ok(count) => {
+            println!("Exported {} profile(s) to {}", count, output.display());
+            0
+        }
+        Err(e) => {
+            eprintln!("Export failed: {}", e);
+            1
+        }
+    }
+}
+
+fn import_bundle(path: &Path, resolution: Option<bundle::Resolution>) -> i32 {
+    let config_path = GAMEMON_CONFIG_FILE.to_string_lossy();
+    let bundle = match bundle::Bundle::load(path) {
+        Ok(bundle) => bundle,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            return 1;
+        }
+    };
+
+    if !bundle.meta.description.is_empty() {
+        println!("{}", bundle.meta.description);
+    }
+    for warning in bundle.warnings() {
+        println!("warning: {}", warning);
+    }
+
+    let collisions = match Config::load_from_file(&config_path) {
+        Ok(config) => bundle.collisions(&config),
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            return 1;
+        }
+    };
+    let resolution = match resolution {
+        Some(resolution) => resolution,
+        None if collisions.is_empty() => bundle::Resolution::Skip,
+        None => {
+            eprintln!("Profiles already exist: {}", collisions.join(", "));
+            eprintln!("Run again with --on-collision rename, merge or skip.");
+            return 1;
+        }
+    };
+
+    match bundle::import_into_file(bundle, &config_path, resolution) {
+        Ok(summary) => {
+            for name in &summary.added {
+                println!("Added {}", name);
+            }
+            for name in &summary.merged {
+                println!("Merged {}", name);
+            }
+            for name in &summary.skipped {
+                println!("Skipped {}", name);
+            }
+            for name in &summary.resources {
+                println!("Added resource {}", name);
+            }
+            for name in &summary.templates {
+                println!("Added template {}", name);
+            }
+            0
+        }
+        Err(e) => {
+            eprintln!("Import failed: {}", e);
+            1
+        }
+    }
+}
+
 fn install_resources() {
     use std::path::PathBuf;
     
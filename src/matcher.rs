@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use glob::Pattern;
+use regex::Regex;
+use crate::config::{Entry, MatchRules};
+use crate::process::{self, ProcessInfo};
+use crate::steam;
+
+/// An entry's match rules, compiled once per config load.
+pub struct EntryMatcher {
+    executable: String,
+    name: Option<String>,
+    path: Option<Pattern>,
+    cmdline: Option<Regex>,
+    parent: Option<String>,
+    app_id: Option<u32>,
+    exclude: Vec<Regex>,
+    /// Set when a pattern failed to compile; such an entry never matches.
+    invalid: bool,
+}
+
+impl EntryMatcher {
+    pub fn new(entry: &Entry) -> Self {
+        let rules = entry.match_rules.clone().unwrap_or_default();
+        let mut invalid = false;
+
+        let path = rules.path.as_deref().and_then(|p| {
+            Pattern::new(p)
+                .map_err(|e| {
+                    log::warn!("Invalid path glob '{}' for '{}': {}", p, entry.game_name, e);
+                    invalid = true;
+                })
+                .ok()
+        });
+
+        let mut compile = |re: &str| {
+            Regex::new(re)
+                .map_err(|e| {
+                    log::warn!("Invalid regex '{}' for '{}': {}", re, entry.game_name, e);
+                    invalid = true;
+                })
+                .ok()
+        };
+
+        let cmdline = rules.cmdline.as_deref().and_then(&mut compile);
+        let exclude = rules.exclude.iter().filter_map(|re| compile(re)).collect();
+
+        Self {
+            executable: if rules.is_empty() { entry.executable.clone() } else { String::new() },
+            name: rules.name,
+            path,
+            cmdline,
+            parent: rules.parent,
+            app_id: rules.app_id,
+            exclude,
+            invalid,
+        }
+    }
+
+    /// Returns true if `proc` belongs to this entry. `by_pid` is used to look up the parent.
+    pub fn matches(&self, proc: &ProcessInfo, by_pid: &HashMap<u32, &ProcessInfo>) -> bool {
+        if self.invalid {
+            return false;
+        }
+
+        let cmdline = proc.cmdline.join(" ");
+        if self.exclude.iter().any(|re| re.is_match(&cmdline)) {
+            return false;
+        }
+
+        if !self.executable.is_empty() {
+            return proc.matches_executable(&self.executable);
+        }
+
+        if let Some(name) = &self.name {
+            if !process::comm_matches(&proc.name, name) {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = &self.path {
+            if !proc.exe.as_deref().is_some_and(|exe| pattern.matches_path(exe)) {
+                return false;
+            }
+        }
+
+        if let Some(re) = &self.cmdline {
+            if !re.is_match(&cmdline) {
+                return false;
+            }
+        }
+
+        if let Some(parent) = &self.parent {
+            let parent_matches = by_pid
+                .get(&proc.ppid)
+                .is_some_and(|p| process::comm_matches(&p.name, parent));
+            if !parent_matches {
+                return false;
+            }
+        }
+
+        if let Some(app_id) = self.app_id {
+            if steam::app_id(proc) != Some(app_id) {
+                return false;
+            }
+        }
+
+        self.name.is_some()
+            || self.path.is_some()
+            || self.cmdline.is_some()
+            || self.parent.is_some()
+            || self.app_id.is_some()
+    }
+
+    /// Returns the PIDs of every process in `processes` that belongs to this entry.
+    pub fn find_pids(&self, processes: &[ProcessInfo]) -> Vec<u32> {
+        let by_pid: HashMap<u32, &ProcessInfo> = processes.iter().map(|p| (p.pid, p)).collect();
+        processes
+            .iter()
+            .filter(|p| self.matches(p, &by_pid))
+            .map(|p| p.pid)
+            .collect()
+    }
+}
+
+
+/// The matchers of a config's entries. The service reloads its config on
+/// every scan, so `update` only compiles entries whose rules changed.
+#[derive(Default)]
+pub struct Matchers {
+    compiled: Vec<Compiled>,
+}
+
+/// One entry's matcher and the fields it was built from.
+struct Compiled {
+    game_name: String,
+    executable: String,
+    rules: Option<MatchRules>,
+    matcher: EntryMatcher,
+}
+
+impl Compiled {
+    fn is_for(&self, entry: &Entry) -> bool {
+        self.game_name == entry.game_name && self.executable == entry.executable && self.rules == entry.match_rules
+    }
+}
+
+impl Matchers {
+    /// Matches `entries` from now on, keeping the matchers of unchanged ones.
+    pub fn update(&mut self, entries: &[Entry]) {
+        let mut previous = std::mem::take(&mut self.compiled);
+        self.compiled = entries
+            .iter()
+            .map(|entry| match previous.iter().position(|c| c.is_for(entry)) {
+                Some(i) => previous.swap_remove(i),
+                None => Compiled {
+                    game_name: entry.game_name.clone(),
+                    executable: entry.executable.clone(),
+                    rules: entry.match_rules.clone(),
+                    matcher: EntryMatcher::new(entry),
+                },
+            })
+            .collect();
+    }
+
+    /// Each entry's game name and matcher, in config order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &EntryMatcher)> {
+        self.compiled.iter().map(|c| (c.game_name.as_str(), &c.matcher))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(game_name: &str, executable: &str) -> Entry {
+        Entry { game_name: game_name.to_string(), executable: executable.to_string(), ..Default::default() }
+    }
+
+    fn process(pid: u32, name: &str) -> ProcessInfo {
+        ProcessInfo { pid, ppid: 1, name: name.to_string(), cmdline: vec![name.to_string()], ..Default::default() }
+    }
+
+    fn running(matchers: &Matchers, processes: &[ProcessInfo]) -> Vec<(String, Vec<u32>)> {
+        matchers.iter().map(|(name, matcher)| (name.to_string(), matcher.find_pids(processes))).collect()
+    }
+
+    #[test]
+    fn update_follows_changed_and_removed_entries() {
+        let processes = [process(10, "dota2"), process(11, "hl2")];
+        let mut matchers = Matchers::default();
+
+        matchers.update(&[entry("Dota", "dota2"), entry("Portal", "portal2")]);
+        assert_eq!(running(&matchers, &processes), [("Dota".to_string(), vec![10]), ("Portal".to_string(), vec![])]);
+
+        matchers.update(&[entry("Portal", "hl2")]);
+        assert_eq!(running(&matchers, &processes), [("Portal".to_string(), vec![11])]);
+
+        let mut portal = entry("Portal", "");
+        portal.match_rules = Some(MatchRules { name: Some("dota2".to_string()), ..Default::default() });
+        matchers.update(&[portal]);
+        assert_eq!(running(&matchers, &processes), [("Portal".to_string(), vec![10])]);
+    }
+
+    #[test]
+    fn update_keeps_the_order_of_the_config() {
+        let mut matchers = Matchers::default();
+        matchers.update(&[entry("A", "a"), entry("B", "b"), entry("C", "c")]);
+        matchers.update(&[entry("C", "c"), entry("A", "a"), entry("B", "b")]);
+        let names: Vec<&str> = matchers.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["C", "A", "B"]);
+    }
+
+    /// Steam's reaper (10) running Dota (11), the same game started by hand
+    /// from a shell (12), and a process whose executable couldn't be read (20).
+    fn table() -> Vec<ProcessInfo> {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        vec![
+            ProcessInfo {
+                pid: 10,
+                ppid: 1,
+                name: "reaper".to_string(),
+                exe: Some("/steam/ubuntu12_32/reaper".into()),
+                cmdline: args(&["reaper", "SteamLaunch", "AppId=570", "--", "/games/dota/game/bin/dota2"]),
+                ..Default::default()
+            },
+            ProcessInfo {
+                pid: 11,
+                ppid: 10,
+                name: "dota2".to_string(),
+                exe: Some("/games/dota/game/bin/dota2".into()),
+                cmdline: args(&["/games/dota/game/bin/dota2", "-novid"]),
+                environ: HashMap::from([("SteamAppId".to_string(), "570".to_string())]),
+            },
+            ProcessInfo {
+                pid: 12,
+                ppid: 500,
+                name: "dota2".to_string(),
+                exe: Some("/home/me/dota2-test/dota2".into()),
+                cmdline: args(&["dota2", "-console"]),
+                ..Default::default()
+            },
+            ProcessInfo { pid: 500, ppid: 1, name: "bash".to_string(), ..Default::default() },
+            ProcessInfo { pid: 20, ppid: 1, name: "hl2".to_string(), exe: None, cmdline: args(&["hl2"]), ..Default::default() },
+        ]
+    }
+
+    fn matching(rules: MatchRules) -> Vec<u32> {
+        let entry = Entry { game_name: "Dota".to_string(), match_rules: Some(rules), ..Default::default() };
+        EntryMatcher::new(&entry).find_pids(&table())
+    }
+
+    fn text(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn name_matches_the_process_name() {
+        assert_eq!(matching(MatchRules { name: text("dota2"), ..Default::default() }), [11, 12]);
+    }
+
+    #[test]
+    fn path_matches_the_executable_path() {
+        assert_eq!(matching(MatchRules { path: text("/games/**/dota2"), ..Default::default() }), [11]);
+        // Without a readable executable there is nothing to match a path against
+        assert_eq!(matching(MatchRules { path: text("**/hl2"), ..Default::default() }), Vec::<u32>::new());
+        assert_eq!(matching(MatchRules { path: text("*"), ..Default::default() }), [10, 11, 12]);
+    }
+
+    #[test]
+    fn cmdline_matches_the_joined_arguments() {
+        assert_eq!(matching(MatchRules { cmdline: text(r"dota2 -console$"), ..Default::default() }), [12]);
+    }
+
+    #[test]
+    fn parent_matches_the_parent_process_name() {
+        assert_eq!(matching(MatchRules { parent: text("reaper"), ..Default::default() }), [11]);
+        assert_eq!(matching(MatchRules { parent: text("bash"), ..Default::default() }), [12]);
+    }
+
+    #[test]
+    fn app_id_matches_environment_and_reaper_arguments() {
+        assert_eq!(matching(MatchRules { app_id: Some(570), ..Default::default() }), [10, 11]);
+        assert_eq!(matching(MatchRules { app_id: Some(440), ..Default::default() }), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn every_rule_that_is_set_must_hold() {
+        let rules = MatchRules { name: text("dota2"), parent: text("reaper"), ..Default::default() };
+        assert_eq!(matching(rules), [11]);
+        let rules = MatchRules { name: text("dota2"), app_id: Some(440), ..Default::default() };
+        assert_eq!(matching(rules), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn exclude_rejects_processes_the_executable_matches() {
+        let entry = Entry {
+            executable: "dota2".to_string(),
+            match_rules: Some(MatchRules { exclude: vec!["-console".to_string()], ..Default::default() }),
+            ..Default::default()
+        };
+        assert_eq!(EntryMatcher::new(&entry).find_pids(&table()), [11]);
+    }
+
+    #[test]
+    fn invalid_patterns_never_match() {
+        let rules = MatchRules { name: text("dota2"), cmdline: text("(unclosed"), ..Default::default() };
+        assert_eq!(matching(rules), Vec::<u32>::new());
+        let rules = MatchRules { name: text("dota2"), path: text("/games/[dota"), ..Default::default() };
+        assert_eq!(matching(rules), Vec::<u32>::new());
+        let rules = MatchRules { name: text("dota2"), exclude: vec!["[".to_string()], ..Default::default() };
+        assert_eq!(matching(rules), Vec::<u32>::new());
+    }
+}
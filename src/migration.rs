@@ -0,0 +1,242 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::{Table, Value};
+use crate::config::{write_atomic, Config};
+
+/// Schema version written by this build. Bump it together with a new entry in `MIGRATIONS`.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: &[fn(&mut Table)] = &[v0_to_v1];
+
+/// Text the GUI's fields used to be pre-filled with, which older versions saved verbatim.
+pub(crate) const GUI_PLACEHOLDERS: &[&str] = &[
+    "Enter game executable...",
+    "Enter start commands...",
+    "Enter end commands...",
+];
+
+/// Reads the schema version of a parsed document. Files from before
+/// versioning have no `version` key and count as version 0.
+pub fn version_of(doc: &Table) -> u32 {
+    doc.get("version")
+        .and_then(Value::as_integer)
+        .and_then(|v| u32::try_from(v).ok())
+        .unwrap_or(0)
+}
+
+/// Upgrades `doc` to `CONFIG_VERSION` one step at a time and returns the
+/// version it started from. Documents from a newer GameMon are rejected
+/// rather than silently downgraded.
+pub fn migrate(doc: &mut Table) -> Result<u32, Box<dyn Error + Send>> {
+    let from = version_of(doc);
+    if from > CONFIG_VERSION {
+        return Err(Box::new(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "config version {} is newer than this GameMon supports (version {})",
+                from, CONFIG_VERSION
+            ),
+        )));
+    }
+
+    for (version, step) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        log::info!("Migrating config from version {} to {}", version, version + 1);
+        step(doc);
+    }
+
+    doc.insert("version".to_string(), Value::Integer(CONFIG_VERSION.into()));
+    Ok(from)
+}
+
+/// Upgrades the file at `path` in place if it is outdated, after copying the
+/// original next to it. Returns the path of the backup, if one was made.
+pub fn migrate_file(path: &Path) -> Result<Option<PathBuf>, Box<dyn Error + Send>> {
+    let data = fs::read_to_string(path).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+    if data.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let mut doc: Table = data.parse().map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+    if version_of(&doc) == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    let from = migrate(&mut doc)?;
+
+    let backup = backup_path(path, from);
+    fs::copy(path, &backup).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+    log::info!("Backed up version {} config to {}", from, backup.display());
+
+    // Round-trip through Config so the file keeps the layout the GUI writes
+    let config: Config = doc.try_into().map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+    let migrated = toml::to_string_pretty(&config).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+    write_atomic(path, migrated.as_bytes()).map_err(|e| Box::new(e) as Box<dyn Error + Send>)?;
+
+    Ok(Some(backup))
+}
+
+/// `config.toml.v0.bak`, or a timestamped name if that backup already exists.
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let backup = path.with_file_name(format!("{}.v{}.bak", file_name, version));
+    if !backup.exists() {
+        return backup;
+    }
+
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    path.with_file_name(format!("{}.v{}.{}.bak", file_name, version, stamp))
+}
+
+/// Version 0 is the unversioned file written up to 0.6. The GUI saved its
+/// placeholder text and blank editor lines as if they were real values.
+fn v0_to_v1(doc: &mut Table) {
+    let Some(Value::Array(entries)) = doc.get_mut("entries") else {
+        return;
+    };
+
+    for entry in entries.iter_mut().filter_map(Value::as_table_mut) {
+        if entry.get("executable").and_then(Value::as_str).is_some_and(|e| GUI_PLACEHOLDERS.contains(&e)) {
+            entry.insert("executable".to_string(), Value::String(String::new()));
+        }
+
+        for key in ["start_commands", "end_commands"] {
+            let commands = entry.entry(key).or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(commands) = commands {
+                commands.retain(|cmd| {
+                    cmd.as_str()
+                        .is_none_or(|cmd| !cmd.trim().is_empty() && !GUI_PLACEHOLDERS.contains(&cmd))
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CommandSpec;
+
+    /// What a fresh install up to 0.6 wrote before any game was added.
+    const V0_EMPTY: &str = "entries = []\n";
+
+    /// Saved by the 0.6 GUI: its placeholder text and blank editor lines
+    /// ended up in the file as if they were real values.
+    const V0_GUI: &str = r#"
+[[entries]]
+game_name = "Elden Ring"
+executable = "eldenring.exe"
+start_commands = ["Enter start commands...", "", "notify-send 'Game on'", "   "]
+end_commands = ["Enter end commands..."]
+
+[[entries]]
+game_name = "New Game"
+executable = "Enter game executable..."
+start_commands = []
+end_commands = []
+"#;
+
+    /// Written by hand against 0.6, with nothing to clean up.
+    const V0_HAND_WRITTEN: &str = r#"
+# Games I play
+[[entries]]
+game_name = "Dota 2"
+executable = "dota2"
+start_commands = ["powerprofilesctl set performance"]
+end_commands = ["powerprofilesctl set balanced"]
+"#;
+
+    fn migrated(text: &str) -> (u32, Config) {
+        let mut doc: Table = text.parse().unwrap();
+        let from = migrate(&mut doc).unwrap();
+        (from, doc.try_into().unwrap())
+    }
+
+    #[test]
+    fn empty_v0_file_gets_a_version() {
+        let (from, config) = migrated(V0_EMPTY);
+        assert_eq!(from, 0);
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert!(config.entries.is_empty());
+    }
+
+    #[test]
+    fn v0_gui_placeholders_and_blank_lines_are_dropped() {
+        let (from, config) = migrated(V0_GUI);
+        assert_eq!(from, 0);
+
+        let elden = &config.entries[0];
+        assert_eq!(elden.executable, "eldenring.exe");
+        assert_eq!(elden.start_commands, vec![CommandSpec::from("notify-send 'Game on'")]);
+        assert!(elden.end_commands.is_empty());
+
+        let new_game = &config.entries[1];
+        assert_eq!(new_game.game_name, "New Game");
+        assert_eq!(new_game.executable, "");
+    }
+
+    #[test]
+    fn v0_hand_written_entries_are_kept() {
+        let (_, config) = migrated(V0_HAND_WRITTEN);
+        let dota = &config.entries[0];
+        assert_eq!((dota.game_name.as_str(), dota.executable.as_str()), ("Dota 2", "dota2"));
+        assert_eq!(dota.start_commands, vec![CommandSpec::from("powerprofilesctl set performance")]);
+        assert_eq!(dota.end_commands, vec![CommandSpec::from("powerprofilesctl set balanced")]);
+    }
+
+    #[test]
+    fn current_version_is_left_alone() {
+        let text = format!("version = {}\n{}", CONFIG_VERSION, V0_GUI);
+        let mut doc: Table = text.parse().unwrap();
+        let original = doc.clone();
+        assert_eq!(migrate(&mut doc).unwrap(), CONFIG_VERSION);
+        assert_eq!(doc, original);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut doc: Table = format!("version = {}\nentries = []\n", CONFIG_VERSION + 1).parse().unwrap();
+        let e = migrate(&mut doc).unwrap_err();
+        assert!(e.to_string().contains("newer than this GameMon supports"), "{}", e);
+    }
+
+    #[test]
+    fn migrate_file_backs_up_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, V0_GUI).unwrap();
+
+        let backup = migrate_file(&path).unwrap().unwrap();
+        assert_eq!(backup, dir.path().join("config.toml.v0.bak"));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V0_GUI);
+
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert_eq!(version_of(&rewritten.parse().unwrap()), CONFIG_VERSION);
+        assert!(!rewritten.contains("Enter start commands..."));
+
+        // Already current, so nothing more to do
+        assert_eq!(migrate_file(&path).unwrap(), None);
+
+        // An older backup is never overwritten
+        fs::write(&path, V0_HAND_WRITTEN).unwrap();
+        let second = migrate_file(&path).unwrap().unwrap();
+        assert_ne!(second, backup);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V0_GUI);
+        assert_eq!(fs::read_to_string(&second).unwrap(), V0_HAND_WRITTEN);
+    }
+
+    #[test]
+    fn migrate_file_leaves_newer_files_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let text = format!("version = {}\nentries = []\n", CONFIG_VERSION + 1);
+        fs::write(&path, &text).unwrap();
+
+        assert!(migrate_file(&path).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        assert!(!dir.path().join(format!("config.toml.v{}.bak", CONFIG_VERSION + 1)).exists());
+    }
+}
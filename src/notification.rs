@@ -0,0 +1,17 @@
+use notify_rust::Notification;
+use crate::config::{APP_NAME, GAMEMON_ICON};
+
+/// Shows a desktop notification. Failures are logged and otherwise ignored,
+/// since there may be no notification daemon at all.
+pub fn notify(summary: &str, body: &str) {
+    let result = Notification::new()
+        .appname(&APP_NAME)
+        .summary(summary)
+        .body(body)
+        .icon(&GAMEMON_ICON.to_string_lossy())
+        .show();
+
+    if let Err(e) = result {
+        log::warn!("Failed to show notification '{}': {}", summary, e);
+    }
+}
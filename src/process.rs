@@ -0,0 +1,351 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::wine;
+
+/// Linux truncates `/proc/<pid>/comm` to 15 bytes (TASK_COMM_LEN - 1).
+const COMM_LEN: usize = 15;
+
+/// Environment variables kept from each process; everything else is dropped.
+const CAPTURED_ENV_PREFIXES: &[&str] = &["STEAM_COMPAT_", "SteamAppId", "SteamGameId", "WINEPREFIX"];
+
+/// A single entry of the process table, as seen by the watchdog.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub ppid: u32,
+    /// Kernel process name (`comm`), possibly truncated.
+    pub name: String,
+    /// Resolved executable path, if we are allowed to read it.
+    pub exe: Option<PathBuf>,
+    pub cmdline: Vec<String>,
+    /// The subset of the environment listed in `CAPTURED_ENV_PREFIXES`.
+    pub environ: HashMap<String, String>,
+}
+
+/// Anything that can produce a snapshot of the running processes.
+///
+/// The watchdog only talks to this trait so a fake table can stand in for `/proc`.
+pub trait ProcessTable {
+    fn snapshot(&self) -> Vec<ProcessInfo>;
+}
+
+/// Reads the process table straight from procfs.
+pub struct ProcScanner {
+    root: PathBuf,
+}
+
+impl Default for ProcScanner {
+    fn default() -> Self {
+        Self { root: PathBuf::from("/proc") }
+    }
+}
+
+impl ProcScanner {
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn read_process(&self, pid: u32) -> Option<ProcessInfo> {
+        let dir = self.root.join(pid.to_string());
+
+        // The process may exit between readdir and here; treat that as "not running".
+        let stat = fs::read_to_string(dir.join("stat")).ok()?;
+        // So is a zombie, though it stays in the table until its parent reaps it
+        if matches!(parse_state(&stat), Some('Z' | 'X')) {
+            return None;
+        }
+        let ppid = parse_ppid(&stat).unwrap_or(0);
+
+        let name = fs::read_to_string(dir.join("comm"))
+            .map(|s| s.trim_end_matches('\n').to_string())
+            .unwrap_or_default();
+
+        let exe = fs::read_link(dir.join("exe")).ok();
+
+        let cmdline = fs::read(dir.join("cmdline"))
+            .map(|raw| {
+                raw.split(|b| *b == 0)
+                    .filter(|arg| !arg.is_empty())
+                    .map(|arg| String::from_utf8_lossy(arg).into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // Only readable for our own processes, which is all we can run hooks for anyway
+        let environ = fs::read(dir.join("environ"))
+            .map(|raw| {
+                raw.split(|b| *b == 0)
+                    .filter_map(|var| parse_env_var(&String::from_utf8_lossy(var)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(ProcessInfo { pid, ppid, name, exe, cmdline, environ })
+    }
+}
+
+impl ProcessTable for ProcScanner {
+    fn snapshot(&self) -> Vec<ProcessInfo> {
+        let Ok(dir) = fs::read_dir(&self.root) else {
+            log::warn!("Unable to read process table at {}", self.root.display());
+            return Vec::new();
+        };
+
+        dir.filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(|pid| self.read_process(pid))
+            .collect()
+    }
+}
+
+/// Process table backed by `sysinfo`, for platforms without procfs.
+#[derive(Default)]
+pub struct SysinfoScanner;
+
+impl ProcessTable for SysinfoScanner {
+    fn snapshot(&self) -> Vec<ProcessInfo> {
+        use sysinfo::{ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System, UpdateKind};
+
+        let mut sys = System::new();
+        sys.refresh_processes_specifics(
+            ProcessesToUpdate::All,
+            true,
+            ProcessRefreshKind::nothing()
+                .with_exe(UpdateKind::Always)
+                .with_cmd(UpdateKind::Always)
+                .with_environ(UpdateKind::Always),
+        );
+
+        sys.processes()
+            .iter()
+            .filter(|(_, proc)| !matches!(proc.status(), ProcessStatus::Zombie | ProcessStatus::Dead))
+            .map(|(pid, proc)| ProcessInfo {
+                pid: pid.as_u32(),
+                ppid: proc.parent().map(|p| p.as_u32()).unwrap_or(0),
+                name: proc.name().to_string_lossy().into_owned(),
+                exe: proc.exe().map(Path::to_path_buf),
+                cmdline: proc.cmd().iter().map(|a| a.to_string_lossy().into_owned()).collect(),
+                environ: proc
+                    .environ()
+                    .iter()
+                    .filter_map(|var| parse_env_var(&var.to_string_lossy()))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Returns the process table implementation for the current platform.
+pub fn system_table() -> Box<dyn ProcessTable + Send> {
+    if cfg!(target_os = "linux") {
+        Box::new(ProcScanner::default())
+    } else {
+        Box::new(SysinfoScanner)
+    }
+}
+
+/// Extracts the parent PID from the contents of `/proc/<pid>/stat`.
+///
+/// The command name is wrapped in parentheses and may itself contain spaces
+/// or parentheses, so parsing starts after the last `)`.
+fn parse_ppid(stat: &str) -> Option<u32> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Extracts the state letter (`R`, `S`, `Z`, ...) from `/proc/<pid>/stat`.
+fn parse_state(stat: &str) -> Option<char> {
+    let rest = &stat[stat.rfind(')')? + 1..];
+    rest.split_whitespace().next()?.chars().next()
+}
+
+/// Splits a `KEY=value` pair, keeping it only if the key is one we capture.
+fn parse_env_var(var: &str) -> Option<(String, String)> {
+    let (key, value) = var.split_once('=')?;
+    CAPTURED_ENV_PREFIXES
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+        .then(|| (key.to_string(), value.to_string()))
+}
+
+fn file_name_of(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+impl ProcessInfo {
+    /// Returns true if this process is `executable`.
+    ///
+    /// A bare name is compared (case-insensitively) with the process name, the
+    /// file name of the resolved executable, the file name of `argv[0]` and,
+    /// for Wine/Proton processes, the Windows image name (`Game.exe`).
+    /// A name containing a path separator is compared with the full paths instead,
+    /// the Windows path for backslashes and the Unix paths otherwise.
+    pub fn matches_executable(&self, executable: &str) -> bool {
+        let executable = executable.trim();
+        if executable.is_empty() {
+            return false;
+        }
+
+        if executable.contains('\\') {
+            return wine::windows_image_path(self).is_some_and(|path| path.eq_ignore_ascii_case(executable));
+        }
+
+        if executable.contains('/') {
+            let exe_matches = self
+                .exe
+                .as_deref()
+                .is_some_and(|exe| exe == Path::new(executable));
+            let argv_matches = self.cmdline.first().is_some_and(|arg0| arg0 == executable);
+            return exe_matches || argv_matches;
+        }
+
+        comm_matches(&self.name, executable)
+            || self
+                .exe
+                .as_deref()
+                .and_then(|exe| exe.file_name())
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.eq_ignore_ascii_case(executable))
+            || self
+                .cmdline
+                .first()
+                .is_some_and(|arg0| file_name_of(arg0).eq_ignore_ascii_case(executable))
+            || wine::windows_image(self).is_some_and(|image| image.eq_ignore_ascii_case(executable))
+    }
+}
+
+/// Compares a process name (`comm`) with `name`, case-insensitively,
+/// allowing for the kernel's truncation of long names.
+pub fn comm_matches(comm: &str, name: &str) -> bool {
+    if comm.eq_ignore_ascii_case(name) {
+        return true;
+    }
+    comm.len() == COMM_LEN
+        && name.len() > COMM_LEN
+        && name.is_char_boundary(COMM_LEN)
+        && name[..COMM_LEN].eq_ignore_ascii_case(comm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn native(name: &str, exe: &str, cmdline: &[&str]) -> ProcessInfo {
+        ProcessInfo {
+            pid: 100,
+            ppid: 1,
+            name: name.to_string(),
+            exe: Some(PathBuf::from(exe)),
+            cmdline: cmdline.iter().map(|arg| arg.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn matches_name_exe_and_argv0_exactly() {
+        let proc = native("dota2", "/opt/dota/game/dota2", &["/opt/dota/game/dota2", "-novid"]);
+        assert!(proc.matches_executable("dota2"));
+        assert!(proc.matches_executable("DOTA2"));
+        assert!(proc.matches_executable(" dota2 "));
+        assert!(proc.matches_executable("/opt/dota/game/dota2"));
+        assert!(!proc.matches_executable("dota"));
+        assert!(!proc.matches_executable("/opt/dota"));
+        assert!(!proc.matches_executable(""));
+
+        // argv[0] counts even when the resolved exe is a wrapper
+        let proc = native("python3", "/usr/bin/python3.12", &["/home/me/bin/launcher", "--game"]);
+        assert!(proc.matches_executable("launcher"));
+        assert!(proc.matches_executable("/home/me/bin/launcher"));
+    }
+
+    #[test]
+    fn file_arguments_named_after_a_game_do_not_match() {
+        let proc = native("vim", "/usr/bin/vim", &["vim", "/home/me/dota-notes.txt"]);
+        assert!(!proc.matches_executable("dota"));
+        assert!(!proc.matches_executable("dota-notes.txt"));
+    }
+
+    #[test]
+    fn wine_processes_match_their_windows_image() {
+        let proc = native("wine64-preload", "/usr/bin/wine64-preloader", &["Z:\\games\\EldenRing\\eldenring.exe"]);
+        assert!(proc.matches_executable("eldenring.exe"));
+        assert!(proc.matches_executable("ELDENRING.EXE"));
+        assert!(proc.matches_executable("z:\\games\\eldenring\\eldenring.exe"));
+        assert!(!proc.matches_executable("C:\\games\\EldenRing\\eldenring.exe"));
+    }
+
+    #[test]
+    fn comm_matches_allows_for_truncation() {
+        assert!(comm_matches("eldenring", "EldenRing"));
+        // 15 bytes is all the kernel keeps
+        assert!(comm_matches("MonsterHunterWo", "MonsterHunterWorld.exe"));
+        assert!(comm_matches("monsterhunterwo", "MonsterHunterWorld.exe"));
+        assert!(!comm_matches("MonsterHunterW", "MonsterHunterWorld.exe"));
+        assert!(!comm_matches("MonsterHunterXX", "MonsterHunterWorld.exe"));
+        assert!(!comm_matches("MonsterHunterWo", "MonsterHunter"));
+        // Byte 15 falls inside `ä`, which must not panic
+        assert!(!comm_matches("abcdefghijklmnä", "abcdefghijklmnäöü"));
+    }
+
+    #[test]
+    fn parse_ppid_skips_any_parentheses_in_comm() {
+        assert_eq!(parse_ppid("1234 (game) S 42 1234 1234 0 -1"), Some(42));
+        assert_eq!(parse_ppid("1234 (my game) S 42 1234"), Some(42));
+        assert_eq!(parse_ppid("1234 (a) b) (c) R 7 1 1"), Some(7));
+        assert_eq!(parse_ppid("1234 ()) S 9 1"), Some(9));
+        assert_eq!(parse_ppid("1234 (game"), None);
+        assert_eq!(parse_ppid("1234 (game) S"), None);
+    }
+
+    #[test]
+    fn scanner_reads_a_proc_fixture() {
+        let root = tempfile::tempdir().unwrap();
+        let write = |pid: &str, file: &str, contents: &[u8]| {
+            let dir = root.path().join(pid);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(file), contents).unwrap();
+        };
+
+        write("100", "stat", b"100 (Game (x64) 2) S 1 100 100 0 -1");
+        write("100", "comm", b"Game (x64) 2\n");
+        write("100", "cmdline", b"/games/game\0--windowed\0");
+        write("100", "environ", b"HOME=/home/me\0SteamAppId=1245620\0WINEPREFIX=/pfx\0");
+        write("101", "stat", b"101 (helper) S 100 100 100 0 -1");
+        // Exited, waiting for its parent to reap it
+        write("103", "stat", b"103 (game) Z) Z 100 100 100 0 -1");
+        write("103", "comm", b"game) Z\n");
+        // Exited between readdir and reading its files
+        write("102", "comm", b"gone\n");
+        write("self", "stat", b"1 (init) S 0");
+
+        let mut snapshot = ProcScanner::with_root(root.path()).snapshot();
+        snapshot.sort_by_key(|proc| proc.pid);
+
+        assert_eq!(snapshot.len(), 2);
+        let game = &snapshot[0];
+        assert_eq!((game.pid, game.ppid), (100, 1));
+        assert_eq!(game.name, "Game (x64) 2");
+        assert_eq!(game.cmdline, vec!["/games/game", "--windowed"]);
+        assert_eq!(game.exe, None);
+        assert_eq!(
+            game.environ,
+            HashMap::from([
+                ("SteamAppId".to_string(), "1245620".to_string()),
+                ("WINEPREFIX".to_string(), "/pfx".to_string()),
+            ])
+        );
+        assert_eq!((snapshot[1].pid, snapshot[1].ppid), (101, 100));
+        assert!(snapshot[1].name.is_empty());
+    }
+
+    #[test]
+    fn parse_state_reads_past_the_comm() {
+        assert_eq!(parse_state("103 (game) Z 100 100 100 0 -1"), Some('Z'));
+        assert_eq!(parse_state("104 (a (Z) b) S 1 104 104"), Some('S'));
+        assert_eq!(parse_state("105 (cut"), None);
+    }
+}
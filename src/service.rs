@@ -1,25 +1,100 @@
-// use sysinfo::{System, Pid};
-use std::{process::Command, sync::{mpsc, Arc}, thread};
-use std::time::Duration;
-use crate::config::Config;
-use dashmap::DashMap;
+use std::{sync::{mpsc::{self, RecvTimeoutError}, Arc}, thread};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::config::{CommandSpec, Companion, Config, Entry, FailurePolicy, Hooks, LogLevel, PeriodicCommand, ProgramCommand, Resource, RunMode, WatchBackend};
+use crate::companion::Supervisor;
+use crate::events::{self, PollWaiter, Waiter};
+use crate::executor::{self, CommandReport, Outcome};
+use crate::matcher::Matchers;
+use crate::notification;
+use crate::process::{self, ProcessInfo};
+use crate::session::{ExitKind, ProcessExit, RefCounter, Schedule, Session, SessionEvent, SessionTracker};
 use rfd::{MessageButtons, MessageDialog, MessageLevel};
-use crate::config::{GAMEMON_CONFIG_FILE, GAMEMON_DIR, check_for_updates};
-#[cfg(windows)]
-use std::os::windows::process::CommandExt;
-use std::collections::HashSet;
+use crate::config::{GAMEMON_CONFIG_DIR, GAMEMON_CONFIG_FILE, GAMEMON_DIR, check_for_updates};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 
+/// Upper bound on an event-driven wait, so config changes are still picked up.
+const EVENT_IDLE_WAIT: Duration = Duration::from_secs(30);
+
 pub fn watchdog() -> Result<(), Box<dyn std::error::Error + Send>> {
     log::info!("Starting watchdog...");
 
-    // Track active monitored executables and their stop signal senders
-    let active_processes: Arc<DashMap<String, Option<mpsc::Sender<()>>>> = Arc::new(DashMap::new());
+    let process_table = process::system_table();
+
+    // Track running sessions and the stop signal senders of their monitor threads
+    let mut sessions = SessionTracker::default();
+    let mut monitors: HashMap<String, mpsc::Sender<Session>> = HashMap::new();
+
+    // The wait backend is (re)created whenever the configured one changes
+    let mut backend: Option<WatchBackend> = None;
+    let mut waiter: Box<dyn Waiter + Send> = Box::new(PollWaiter);
+
+    // Shared with monitor threads to tell the first and last running session apart
+    let shared = Arc::new(RefCounter::default());
+    let mut service_started = false;
 
-    let mut update_timer = 600;
+    let mut last_update_check: Option<Instant> = None;
+    let mut log_level: Option<LogLevel> = None;
+
+    // The last config that loaded, kept while the file on disk is broken
+    let mut config = Config::default();
+    let mut config_error: Option<String> = None;
+    let mut matchers = Matchers::default();
 
     loop {
-        if update_timer >= 600 {
+        // Reload config each loop
+        let config_path = &GAMEMON_CONFIG_FILE.to_string_lossy();
+        // Entries are flattened here so a bad `extends` is handled like any other config error
+        let loaded = Config::load_from_file(config_path).and_then(|config| {
+            config.resolved().map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)
+        });
+        match loaded {
+            Ok(loaded) => {
+                if config_error.take().is_some() {
+                    log::info!("✅ Config is valid again, reloading.");
+                }
+                config = loaded;
+                matchers.update(&config.entries);
+            }
+            Err(e) => {
+                // Report each distinct error once rather than on every scan
+                let message = e.to_string();
+                if config_error.as_ref() != Some(&message) {
+                    log::warn!("⚠️ Invalid config {}: {}. Keeping the last valid config.", config_path, message);
+                    if config.settings.notifications.enabled {
+                        notification::notify(
+                            "GameMon config error",
+                            &format!("{}\nStill using the last valid config.", message),
+                        );
+                    }
+                    config_error = Some(message);
+                }
+            }
+        }
+
+        // Wait for a config that loads, rather than skipping the hooks of a broken one
+        if !service_started && config_error.is_none() {
+            run_hooks("on_service_start", &config.hooks.on_service_start, &CommandContext::global(), CommandOptions::new(&config, None));
+            service_started = true;
+        }
+
+        let grace: HashMap<String, Duration> = config
+            .entries
+            .iter()
+            .map(|entry| (entry.game_name.clone(), config.end_grace(entry)))
+            .collect();
+        let entries = &config.entries;
+        let notifications = &config.settings.notifications;
+
+        if log_level != Some(config.settings.log_level) {
+            log::set_max_level(config.settings.log_level.filter());
+            log_level = Some(config.settings.log_level);
+        }
+
+        let update_interval = Duration::from_secs(config.service.update_check_interval);
+        if config.settings.updates.auto_check
+            && !update_interval.is_zero()
+            && last_update_check.is_none_or(|t| t.elapsed() >= update_interval) {
             let update_marker = GAMEMON_DIR.join(".update-pending");
             let newly_updated_marker = GAMEMON_DIR.join(".update-complete");
             if newly_updated_marker.exists() {
@@ -46,204 +121,759 @@ pub fn watchdog() -> Result<(), Box<dyn std::error::Error + Send>> {
                 Err(e) => log::error!("Error checking for updates: {:?}\n", e),
             }
 
-            update_timer = 0;
+            last_update_check = Some(Instant::now());
         }
 
-        // Reload config each loop
-        let config_path = &GAMEMON_CONFIG_FILE.to_string_lossy();
-        let config = Config::load_from_file(config_path)?;
-        let entries = config.entries;
-
-        // Get ps aux output once
-        let ps_output = get_ps_aux_output().unwrap_or_default();
-
-        // Collect currently running executables by checking if the exec name appears in ps output
-        let mut running_executables = HashSet::new();
-
-        for entry in &entries {
-            if is_executable_running(&entry.executable, &ps_output) {
-                // log::info!("✅ Match found for {}", &entry.executable);
-                running_executables.insert(entry.executable.clone());
-            } else {
-                // log::info!("❌ No match found for {}", &entry.executable);
-            }
+        if backend != Some(config.service.backend) {
+            log::info!("Using the {:?} process detection backend.", config.service.backend);
+            waiter = events::waiter_for(config.service.backend);
+            backend = Some(config.service.backend);
         }
 
-        // For each entry, manage starting or stopping monitoring threads
-        for entry in &entries {
-            let is_running = running_executables.contains(&entry.executable);
-            let is_monitored = active_processes.contains_key(&entry.executable);
+        // Take one snapshot of the process table per poll
+        let processes = process_table.snapshot();
+
+        // Collect currently running games along with the PIDs that matched
+        let running_games = find_running(&matchers, &processes);
+
+        // Fold the matches into sessions and start or stop monitor threads accordingly
+        for event in sessions.update(&running_games, &processes, &grace, Instant::now()) {
+            match event {
+                SessionEvent::Started(session) => {
+                    let Some(entry) = entries.iter().find(|e| e.game_name == session.game_name) else {
+                        continue;
+                    };
+
+                    log::info!(
+                        "Detected '{}' is running (root PID {}, PIDs {:?}), starting monitor...",
+                        session.game_name,
+                        session.root_pid,
+                        session.pids
+                    );
+
+                    if notifications.enabled && notifications.session_start {
+                        notification::notify("Game started", &format!("{} is running.", session.game_name));
+                    }
 
-            if is_running && !is_monitored {
-                log::info!("Detected process '{}' is running, starting monitor...", &entry.executable);
+                    let (tx, rx) = mpsc::channel();
+                    monitors.insert(session.game_name.clone(), tx);
 
-                let (tx, rx) = mpsc::channel();
-                active_processes.insert(entry.executable.clone(), Some(tx));
+                    let plan = SessionPlan::new(&config, entry);
+                    let shared = shared.clone();
 
-                let executable_name = entry.executable.clone();
-                let start_commands = entry.start_commands.clone();
-                let end_commands = entry.end_commands.clone();
-                let active_processes_clone = Arc::clone(&active_processes);
+                    thread::spawn(move || {
+                        monitor_process(session, plan, shared, rx);
+                    });
+                }
+                SessionEvent::Resumed(session) => {
+                    log::info!("'{}' came back within its grace period, resuming session.", session.game_name);
+                }
+                SessionEvent::Ended(session) => {
+                    if notifications.enabled && notifications.session_end {
+                        notification::notify("Game ended", &format!("{} has exited.", session.game_name));
+                    }
 
-                thread::spawn(move || {
-                    monitor_process(executable_name, start_commands, end_commands, active_processes_clone, rx);
-                });
-            } else if !is_running && is_monitored {
-                if let Some(mut sender) = active_processes.get_mut(&entry.executable) {
-                    if let Some(tx) = sender.take() {
-                        log::info!("Process '{}' stopped, sending termination signal...", &entry.executable);
-                        tx.send(()).unwrap_or_else(|_| {
-                            log::info!("Failed to send termination signal to '{}', likely already closed.", &entry.executable);
+                    if let Some(tx) = monitors.remove(&session.game_name) {
+                        log::info!("'{}' stopped, sending termination signal...", session.game_name);
+                        let game_name = session.game_name.clone();
+                        tx.send(session).unwrap_or_else(|_| {
+                            log::info!("Failed to send termination signal to '{}', likely already closed.", game_name);
                         });
                     }
                 }
             }
         }
 
-        thread::sleep(Duration::from_secs(5));
-        update_timer += 5;
+        // With kernel events there is nothing to poll for while idle, but a
+        // session in its grace period still needs a timely rescan.
+        let timeout = if waiter.is_event_driven() && !sessions.has_pending_end() {
+            EVENT_IDLE_WAIT
+        } else {
+            config.service.poll_timeout(sessions.is_idle(), sessions.has_pending_end())
+        };
+        waiter.wait(&sessions.pids(), timeout);
+        sessions.record_exits(&waiter.take_exits());
     }
 }
 
-/// Runs `ps aux` and returns the output as a String
-fn get_ps_aux_output() -> Option<String> {
-    Command::new("sh")
-        .arg("-c")
-        .arg("ps aux")
-        .output()
-        .ok()
-        .and_then(|output| String::from_utf8(output.stdout).ok())
+/// Maps each entry's game name to the PIDs matching its rules.
+/// Entries with no matching process are left out.
+pub fn find_running(matchers: &Matchers, processes: &[ProcessInfo]) -> HashMap<String, Vec<u32>> {
+    let mut running = HashMap::new();
+
+    for (game_name, matcher) in matchers.iter() {
+        let pids = matcher.find_pids(processes);
+        if !pids.is_empty() {
+            running.insert(game_name.to_string(), pids);
+        }
+    }
+
+    running
 }
 
-/// Returns true if the executable name is found in the `ps aux` output (case-insensitive),
-/// excluding lines containing 'grep' to avoid false positives.
-fn is_executable_running(executable: &str, ps_output: &str) -> bool {
-    let exec_lower = executable.to_lowercase();
-    ps_output
-        .to_lowercase()
-        .lines()
-        .any(|line| !line.contains("grep") && line.contains(&exec_lower))
+/// Key in the shared `RefCounter` counting running sessions.
+const SESSIONS: &str = "sessions";
+
+/// What a monitor thread runs for one session, taken from the config when it starts.
+struct SessionPlan {
+    entry: Entry,
+    hooks: Hooks,
+    /// The entry's resources with their definitions, in the order they are listed.
+    resources: Vec<(String, Resource)>,
+    /// For the entry's own commands and its resources.
+    options: CommandOptions,
+    /// For the global hooks.
+    hook_options: CommandOptions,
 }
 
-// Get the PID for a given executable name
-// #[cfg(unix)]
-// fn get_pid_for_executable(executable: &str, sys: &System) -> Option<Pid> {
-//     let target = executable.to_ascii_lowercase();
+impl SessionPlan {
+    fn new(config: &Config, entry: &Entry) -> Self {
+        let resources = entry
+            .resources
+            .iter()
+            .filter_map(|name| match config.resources.get(name) {
+                Some(resource) => Some((name.clone(), resource.clone())),
+                None => {
+                    log::warn!("'{}' uses unknown resource '{}', ignoring it.", entry.game_name, name);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            entry: entry.clone(),
+            hooks: config.hooks.clone(),
+            resources,
+            options: CommandOptions::new(config, Some(entry)),
+            hook_options: CommandOptions::new(config, None),
+        }
+    }
+}
+
+// Monitor a session and execute end commands once its process tree exits.
+// The global hooks and shared resources wrap the entry's own commands:
+// first-session and any-start hooks, then resource acquires, run before its
+// start commands; resource releases, then any-end and last-session hooks,
+// run after its end commands. Companions and while_running commands run
+// between the two, and after a crash the on_crash commands run before the
+// end commands.
+fn monitor_process(
+    session: Session,
+    plan: SessionPlan,
+    shared: Arc<RefCounter>,
+    rx: mpsc::Receiver<Session>,
+) {
+    let SessionPlan { entry, hooks, resources, options, hook_options } = plan;
+
+    let started_at = SystemTime::now();
+    let started = Instant::now();
+    let context = CommandContext::for_session(&entry, &session, started_at);
+    shared.acquire(SESSIONS, || run_hooks("on_first_session_start", &hooks.on_first_session_start, &context, hook_options));
+    run_hooks("on_any_start", &hooks.on_any_start, &context, hook_options);
+
+    for (name, resource) in &resources {
+        let holders = shared.acquire(&resource_key(name), || {
+            log::info!("Acquiring resource '{}'...", name);
+            run_commands(&resource.acquire, &context, options.continuing());
+        });
+        log::debug!("Resource '{}' now held by {} session(s).", name, holders);
+    }
+
+    let rolled_back = run_start_commands(&entry, &context, options);
+
+    // Nothing to accompany once the start has been undone
+    let companions = (!rolled_back && !entry.companions.is_empty()).then(|| {
+        let companions: Vec<Companion> = entry
+            .companions
+            .iter()
+            .map(|companion| Companion { command: context.expand_command(&companion.command), ..companion.clone() })
+            .collect();
+        Supervisor::start(&session.game_name, &companions, &context.env())
+    });
+
+    log::info!("Monitoring '{}'. Waiting for termination signal...", session.game_name);
+
+    // Run the while_running commands on their timers until the session ends,
+    // handing `Schedule` the current time on every call
+    let periodic = if rolled_back { &[][..] } else { &entry.while_running[..] };
+    let mut schedule = Schedule::new(periodic.iter().map(PeriodicCommand::interval), Instant::now());
+    let session = loop {
+        let received = match schedule.wait_time(Instant::now()) {
+            Some(wait) => rx.recv_timeout(wait),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match received {
+            Ok(ended) => {
+                log::info!("Received termination signal for '{}'.", ended.game_name);
+                break ended;
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                for i in schedule.take_due(Instant::now()) {
+                    log::debug!("Running while_running command for '{}'.", session.game_name);
+                    run_commands(std::slice::from_ref(&periodic[i].command), &context, options.continuing());
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                log::warn!("Watchdog went away while monitoring '{}'.", session.game_name);
+                break session;
+            }
+        }
+    };
 
-//     for (pid, proc) in sys.processes() {
-//         // Convert OsStr process name to string lossily
-//         let proc_name_cow: Cow<str> = proc.name().to_string_lossy();
-//         let proc_name = proc_name_cow.to_ascii_lowercase();
+    if let Some(companions) = companions {
+        companions.stop();
+    }
 
+    let context = CommandContext::for_session(&entry, &session, started_at).with_duration(started.elapsed());
+    match session.exit {
+        Some(exit) if exit.kind() == ExitKind::Crashed => {
+            log::warn!("💥 '{}' crashed ({}), running crash commands...", session.game_name, describe_exit(&exit));
+            run_commands(&entry.on_crash, &context, options.continuing());
+            run_hooks("on_crash", &hooks.on_crash, &context, hook_options);
+        }
+        Some(exit) => log::info!("'{}' exited: {}.", session.game_name, describe_exit(&exit)),
+        None => log::debug!("Exit status of '{}' is unknown.", session.game_name),
+    }
 
-//         if proc_name == target {
-//             log::info!("✅ Found '{}' as PID {}", target, pid);
-//             return Some(*pid);
-//         }
-//     }
+    if rolled_back {
+        log::info!("Start of '{}' was rolled back, skipping its end commands.", session.game_name);
+    } else {
+        run_commands(&entry.end_commands, &context, options.continuing());
+    }
 
-//     None
-// }
+    // Released in reverse, so resources depending on earlier ones go first
+    for (name, resource) in resources.iter().rev() {
+        let holders = shared.release(&resource_key(name), || {
+            log::info!("Releasing resource '{}'...", name);
+            run_commands(&resource.release, &context, options.continuing());
+        });
+        if holders > 0 {
+            log::info!("Resource '{}' is still held by {} session(s), not releasing.", name, holders);
+        }
+    }
 
-#[cfg(windows)]
-fn get_pid_for_executable(_executable: &str, _sys: &System) -> Option<Pid> {
-    // TODO: Implement Windows process lookup here
-    log::info!("Windows version of get_pid_for_executable not implemented yet.");
-    None
+    run_hooks("on_any_end", &hooks.on_any_end, &context, hook_options);
+    shared.release(SESSIONS, || run_hooks("on_last_session_end", &hooks.on_last_session_end, &context, hook_options));
+
+    log::info!("Removed '{}' from active monitoring.", session.game_name);
 }
 
+fn describe_exit(exit: &ProcessExit) -> String {
+    match (exit.code, exit.signal) {
+        (Some(code), _) => format!("exit code {}", code),
+        (None, Some(signal)) if exit.core_dumped => format!("signal {}, core dumped", signal),
+        (None, Some(signal)) => format!("signal {}", signal),
+        (None, None) => exit.kind().to_string(),
+    }
+}
 
-// Monitor a process and execute end commands when it exits
-fn monitor_process(
-    executable_name: String,
-    start_commands: Vec<String>,
-    end_commands: Vec<String>,
-    active_processes: Arc<DashMap<String, Option<mpsc::Sender<()>>>>,
-    rx: mpsc::Receiver<()>,
-) {
-    if let Err(e) = run_commands(&start_commands) {
-        log::error!("Error running start commands: {}", e);
+fn resource_key(name: &str) -> String {
+    format!("resource:{}", name)
+}
+
+/// Runs one list of global hooks, logging instead of failing.
+pub fn run_hooks(name: &str, commands: &[CommandSpec], context: &CommandContext, options: CommandOptions) {
+    if commands.is_empty() {
+        return;
     }
 
-    log::info!("Monitoring process '{}'. Waiting for termination signal...", executable_name);
+    log::info!("Running {} hooks...", name);
+    run_commands(commands, context, options);
+}
 
-    loop {
-        match rx.recv() {
-            Ok(_) => {
-                log::info!("Received termination signal for process '{}'.", executable_name);
-                break;
+/// Applies to every command in a list: the time limit for commands that
+/// don't set their own, how the list runs, and whether a timeout shows a
+/// notification. Global hooks use the defaults of `mode` and `on_failure`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CommandOptions {
+    pub timeout: Option<Duration>,
+    pub mode: RunMode,
+    pub on_failure: FailurePolicy,
+    pub notify: bool,
+}
+
+impl CommandOptions {
+    /// Options for the commands of `entry`, or for global hooks when `None`.
+    pub fn new(config: &Config, entry: Option<&Entry>) -> Self {
+        Self {
+            timeout: config.command_timeout(entry),
+            mode: entry.map(|entry| entry.mode).unwrap_or_default(),
+            on_failure: entry.map(|entry| entry.on_failure).unwrap_or_default(),
+            notify: config.settings.notifications.enabled,
+        }
+    }
+
+    /// The same options, but running every command whatever happens.
+    fn continuing(self) -> Self {
+        Self { on_failure: FailurePolicy::Continue, ..self }
+    }
+}
+
+// Run a list of commands with their placeholders filled in from `context`
+pub fn run_commands(commands: &[CommandSpec], context: &CommandContext, options: CommandOptions) -> Vec<CommandReport> {
+    let stop_on_failure = options.on_failure != FailurePolicy::Continue;
+    let expanded: Vec<CommandSpec> = commands.iter().map(|spec| context.expand_command(spec)).collect();
+    let reports = executor::run_all(&expanded, &context.env(), options.timeout, options.mode, stop_on_failure);
+
+    for report in &reports {
+        log::debug!("{}", report);
+        if let Outcome::TimedOut(limit) = report.outcome {
+            if options.notify {
+                notification::notify(
+                    "GameMon command timed out",
+                    &format!("{}\nStopped after {}s.", report.command, limit.as_secs()),
+                );
             }
-            Err(_) => continue,
         }
     }
 
-    if let Err(e) = run_commands(&end_commands) {
-        log::error!("Error running end commands: {}", e);
+    let failed = reports.iter().filter(|report| !report.succeeded()).count();
+    if failed > 0 {
+        log::warn!(
+            "⚠️ {} of {} command(s) failed{}.",
+            failed,
+            commands.len(),
+            if reports.len() < commands.len() { ", the rest were skipped" } else { "" }
+        );
     }
 
-    active_processes.remove(&executable_name);
-    log::info!("Removed '{}' from active monitoring.", executable_name);
+    reports
 }
 
-// Run a list of commands
-pub fn run_commands(commands: &[String]) -> Result<(), Box<dyn std::error::Error + Send>> {
-    for cmd in commands {
-        let cmd_string = cmd.to_string();
+/// Runs the start commands of `entry`. When one fails and the entry's
+/// `on_failure` is `rollback`, the undo commands of the start commands that
+/// succeeded run right away, newest first. Returns true if the start was
+/// rolled back.
+fn run_start_commands(entry: &Entry, context: &CommandContext, options: CommandOptions) -> bool {
+    let reports = run_commands(&entry.start_commands, context, options);
+    if options.on_failure != FailurePolicy::Rollback || reports.iter().all(CommandReport::succeeded) {
+        return false;
+    }
 
-        #[cfg(windows)]
-        {
-            match config::run_windows_cmd(&cmd_string) {
-                Ok(_) => log::info!("{:?} executed successfully", &cmd_string),
-                Err(e) => log::error!("Failed to execute command '{}': {}", &cmd_string, e),
-            }
+    let undo: Vec<CommandSpec> = reports
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, report)| report.succeeded())
+        .filter_map(|(i, _)| entry.undo.get(i).cloned().flatten())
+        .collect();
+
+    log::warn!("↩️ A start command for '{}' failed, rolling back {} command(s)...", entry.game_name, undo.len());
+    run_commands(&undo, context, options.continuing());
+    true
+}
+
+/// Built-in variables commands can use, as `{name}` placeholders and as
+/// `GAMEMON_<NAME>` in their environment. `duration` and the `exit_*` ones
+/// are only known once the session has ended.
+pub const BUILTIN_VARS: &[&str] = &[
+    "config_dir",
+    "game_name",
+    "executable",
+    "root_pid",
+    "pids",
+    "start_time",
+    "duration",
+    "exit_status",
+    "exit_code",
+    "exit_signal",
+];
+
+/// What a list of commands runs in: the built-in variables of its session
+/// plus the entry's `vars`, which are exported as `GAMEMON_VAR_<NAME>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandContext {
+    /// In the order they are exported.
+    builtins: Vec<(&'static str, String)>,
+    vars: BTreeMap<String, String>,
+}
+
+impl CommandContext {
+    /// For global hooks that don't belong to a session.
+    pub fn global() -> Self {
+        Self {
+            builtins: vec![("config_dir", GAMEMON_CONFIG_DIR.to_string_lossy().into_owned())],
+            vars: BTreeMap::new(),
         }
+    }
+
+    /// For running the commands of `entry` outside a session, as the tray and
+    /// the GUI's Run buttons do. There are no PIDs, so those expand to nothing.
+    pub fn for_entry(entry: &Entry, started: SystemTime) -> Self {
+        let mut context = Self::global();
+        context.set("game_name", entry.game_name.clone());
+        context.set("executable", entry.executable.clone());
+        context.set("root_pid", String::new());
+        context.set("pids", String::new());
+        context.set("start_time", unix_seconds(started));
+        context.vars = entry.vars.clone();
+        context
+    }
 
-        #[cfg(unix)]
-        {
-            run_shell_command(&cmd_string);
+    /// For the commands of a running or ended `session` of `entry`.
+    pub fn for_session(entry: &Entry, session: &Session, started: SystemTime) -> Self {
+        let mut context = Self::for_entry(entry, started);
+        let pids: Vec<String> = session.pids.iter().map(u32::to_string).collect();
+        context.set("root_pid", session.root_pid.to_string());
+        context.set("pids", pids.join(" "));
+
+        if let Some(exit) = &session.exit {
+            context.set("exit_status", exit.kind().to_string());
+            if let Some(code) = exit.code {
+                context.set("exit_code", code.to_string());
+            }
+            if let Some(signal) = exit.signal {
+                context.set("exit_signal", signal.to_string());
+            }
         }
+        context
     }
-    Ok(())
-}
 
-use std::process::Output;
-use std::io;
+    /// Adds how long the session ran, in whole seconds.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.set("duration", duration.as_secs().to_string());
+        self
+    }
 
-/// Executes a shell command string and prints stdout/stderr.
-/// Returns true if the command succeeded.
-pub fn run_shell_command(command_str: &str) -> bool {
-    if command_str.trim().is_empty() {
-        log::warn!("⚠️ Empty command string provided, skipping execution.");
-        return false;
+    fn set(&mut self, name: &'static str, value: String) {
+        match self.builtins.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, old)) => *old = value,
+            None => self.builtins.push((name, value)),
+        }
+    }
+
+    /// The value of placeholder `name`. Built-ins win over the entry's `vars`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.builtins
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, value)| value.as_str())
+            .or_else(|| self.vars.get(name).map(String::as_str))
     }
 
-    log::info!("🟢 Running command: {}", command_str);
+    /// Environment passed to the commands.
+    pub fn env(&self) -> Vec<(String, String)> {
+        let builtins = self.builtins.iter().map(|(name, value)| (format!("GAMEMON_{}", name.to_uppercase()), value.clone()));
+        let vars = self.vars.iter().map(|(name, value)| (format!("GAMEMON_VAR_{}", name.to_uppercase()), value.clone()));
+        builtins.chain(vars).collect()
+    }
 
-    let output: io::Result<Output> = Command::new("sh")
-        .arg("-c")
-        .arg(command_str)
-        .output();
+    /// `spec` with its placeholders filled in. Values are quoted wherever a
+    /// shell will read them, so a game called `Baldur's Gate` can't break the
+    /// command line; arguments of program commands are passed as they are.
+    pub fn expand_command(&self, spec: &CommandSpec) -> CommandSpec {
+        match spec {
+            CommandSpec::Shell(cmd) => CommandSpec::Shell(self.expand(cmd, true)),
+            CommandSpec::Program(program) => CommandSpec::Program(ProgramCommand {
+                program: self.expand(&program.program, program.shell),
+                args: program.args.iter().map(|arg| self.expand(arg, false)).collect(),
+                cwd: program.cwd.as_ref().map(|cwd| self.expand(cwd, false)),
+                env: program.env.iter().map(|(k, v)| (k.clone(), self.expand(v, false))).collect(),
+                ..program.clone()
+            }),
+        }
+    }
 
-    match output {
-        Ok(output) => {
-            if !output.stdout.is_empty() {
-                log::info!("✅ STDOUT:\n{}", String::from_utf8_lossy(&output.stdout));
+    /// Replaces each `{name}` that names a variable with its value, shell
+    /// quoted if `quote`: as a word of its own, or escaped for the quotes
+    /// the placeholder is in. `{{name}}` gives a literal `{name}`; braces
+    /// around anything else, like `${HOME}` or an awk script, are left alone.
+    pub fn expand(&self, text: &str, quote: bool) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        let mut rest = text;
+        let mut quoting = Quoting::None;
+
+        while let Some(open) = rest.find('{') {
+            expanded.push_str(&rest[..open]);
+            quoting = quoting.after(&rest[..open]);
+            rest = &rest[open..];
+
+            let escaped = rest
+                .strip_prefix("{{")
+                .and_then(placeholder)
+                .and_then(|(name, tail)| Some((name, tail.strip_prefix('}')?)))
+                .filter(|(name, _)| self.get(name).is_some());
+            if let Some((name, tail)) = escaped {
+                expanded.push('{');
+                expanded.push_str(name);
+                expanded.push('}');
+                rest = tail;
+                continue;
             }
-            if !output.stderr.is_empty() {
-                log::error!("⚠️ STDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+
+            let value = rest
+                .strip_prefix('{')
+                .and_then(placeholder)
+                .and_then(|(name, tail)| Some((self.get(name)?, tail)));
+            match value {
+                Some((value, tail)) if quote => {
+                    expanded.push_str(&shell_quote(value, quoting));
+                    rest = tail;
+                }
+                Some((value, tail)) => {
+                    expanded.push_str(value);
+                    rest = tail;
+                }
+                None => {
+                    expanded.push('{');
+                    rest = &rest[1..];
+                }
             }
+        }
+
+        expanded.push_str(rest);
+        expanded
+    }
+}
 
-            if output.status.success() {
-                log::info!("✅ Command executed successfully.");
-                true
-            } else {
-                log::error!("❌ Command exited with status: {}", output.status);
-                false
+/// Splits `name}rest` into the name and what follows the brace, if `name` is
+/// made of letters, digits and underscores.
+fn placeholder(text: &str) -> Option<(&str, &str)> {
+    let (name, rest) = text.split_once('}')?;
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then_some((name, rest))
+}
+
+/// The quotes a shell command line is inside at some point of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Quoting {
+    None,
+    Single,
+    Double,
+}
+
+impl Quoting {
+    /// The quotes still open after `text`, starting inside `self`. Only `sh`
+    /// has single quotes and backslash escapes; `cmd` has neither.
+    fn after(self, text: &str) -> Self {
+        let mut quoting = self;
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            quoting = match (quoting, c) {
+                (Quoting::None | Quoting::Double, '\\') if cfg!(unix) => {
+                    chars.next();
+                    quoting
+                }
+                (Quoting::None, '\'') if cfg!(unix) => Quoting::Single,
+                (Quoting::Single, '\'') => Quoting::None,
+                (Quoting::None, '"') => Quoting::Double,
+                (Quoting::Double, '"') => Quoting::None,
+                _ => quoting,
+            };
+        }
+        quoting
+    }
+}
+
+/// `value` for `sh`, inside `quoting`. Outside quotes it becomes a single
+/// word, quoted only if it needs to be; inside quotes it is escaped so it
+/// can't end them.
+#[cfg(unix)]
+fn shell_quote(value: &str, quoting: Quoting) -> String {
+    match quoting {
+        Quoting::None => {
+            let plain = |c: char| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c);
+            if !value.is_empty() && value.chars().all(plain) {
+                return value.to_string();
             }
+            format!("'{}'", value.replace('\'', "'\\''"))
         }
-        Err(e) => {
-            log::error!("❌ Failed to execute command '{}': {}", command_str, e);
-            false
+        Quoting::Single => value.replace('\'', "'\\''"),
+        Quoting::Double => value.chars().fold(String::with_capacity(value.len()), |mut escaped, c| {
+            if matches!(c, '"' | '\\' | '$' | '`') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        }),
+    }
+}
+
+/// `value` for `cmd`, which has no way to escape a quote, so any are dropped.
+#[cfg(windows)]
+fn shell_quote(value: &str, quoting: Quoting) -> String {
+    let value = value.replace('"', "");
+    match quoting {
+        Quoting::Double => value,
+        _ => format!("\"{}\"", value),
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> CommandContext {
+        let entry = Entry {
+            game_name: "Baldur's Gate".to_string(),
+            executable: "bg3".to_string(),
+            vars: BTreeMap::from([("fan".to_string(), "full speed".to_string())]),
+            ..Default::default()
+        };
+        CommandContext::for_entry(&entry, UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_commands_get_quoted_values() {
+        let context = context();
+        assert_eq!(context.expand("notify-send {game_name}", true), r#"notify-send 'Baldur'\''s Gate'"#);
+        assert_eq!(context.expand("fanctl {fan} {executable}", true), "fanctl 'full speed' bg3");
+        assert_eq!(context.expand("echo {start_time}", true), "echo 1700000000");
+        // PIDs aren't known outside a session
+        assert_eq!(context.expand("kill {root_pid}", true), "kill ''");
+    }
+
+    #[test]
+    fn escaped_unknown_and_shell_braces_are_left_alone() {
+        let context = context();
+        assert_eq!(context.expand("echo {{game_name}}", true), "echo {game_name}");
+        assert_eq!(context.expand("echo {x} {{x}}", true), "echo {x} {{x}}");
+        assert_eq!(context.expand("cd ${HOME} && awk '{print $1}'", true), "cd ${HOME} && awk '{print $1}'");
+        assert_eq!(context.expand("{game_name", true), "{game_name");
+    }
+
+    #[test]
+    fn program_arguments_are_not_quoted() {
+        let spec = CommandSpec::from_line(
+            r#"{ program = "notify-send", args = ["{game_name}", "{fan}"], env = { GAME = "{game_name}" } }"#,
+        );
+        let CommandSpec::Program(program) = context().expand_command(&spec) else {
+            panic!("expected a program command");
+        };
+        assert_eq!(program.args, ["Baldur's Gate", "full speed"]);
+        assert_eq!(program.env["GAME"], "Baldur's Gate");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn program_run_through_a_shell_is_quoted() {
+        let spec = CommandSpec::from_line(r#"{ program = "echo {game_name} \"$1\"", args = ["{game_name}"], shell = true }"#);
+        let CommandSpec::Program(program) = context().expand_command(&spec) else {
+            panic!("expected a program command");
+        };
+        assert_eq!(program.program, r#"echo 'Baldur'\''s Gate' "$1""#);
+        assert_eq!(program.args, ["Baldur's Gate"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn placeholders_inside_quotes_are_escaped_for_them() {
+        let context = context();
+        assert_eq!(context.expand(r#"notify-send "Playing {game_name}""#, true), r#"notify-send "Playing Baldur's Gate""#);
+        assert_eq!(context.expand("notify-send 'Playing {game_name}'", true), r#"notify-send 'Playing Baldur'\''s Gate'"#);
+        // Escaped and closed quotes don't count
+        assert_eq!(context.expand(r#"echo \" {fan} "\"" {fan}"#, true), r#"echo \" 'full speed' "\"" 'full speed'"#);
+        assert_eq!(context.expand(r#"echo "it's" {fan}"#, true), r#"echo "it's" 'full speed'"#);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn quoted_values_reach_the_shell_unchanged() {
+        let tricky = r#"a'b"c$HOME`true`\d {e}"#;
+        let entry = Entry { game_name: tricky.to_string(), ..Default::default() };
+        let context = CommandContext::for_entry(&entry, UNIX_EPOCH);
+        for template in ["printf %s {game_name}", r#"printf %s "<{game_name}>""#, "printf %s '<{game_name}>'"] {
+            let output = std::process::Command::new("sh").arg("-c").arg(context.expand(template, true)).output().unwrap();
+            let expected = if template.ends_with("{game_name}") { tricky.to_string() } else { format!("<{}>", tricky) };
+            assert_eq!(String::from_utf8_lossy(&output.stdout), expected, "{}", template);
         }
     }
+
+    #[test]
+    fn env_exports_builtins_and_vars() {
+        let env = context().with_duration(Duration::from_secs(90)).env();
+        assert!(env.contains(&("GAMEMON_GAME_NAME".to_string(), "Baldur's Gate".to_string())));
+        assert!(env.contains(&("GAMEMON_DURATION".to_string(), "90".to_string())));
+        assert!(env.contains(&("GAMEMON_VAR_FAN".to_string(), "full speed".to_string())));
+    }
+
+    /// Runs the start commands of `name` in `config`, resolved as the watchdog
+    /// would, and returns whether they were rolled back and what the commands
+    /// appended to `log`.
+    #[cfg(unix)]
+    fn start(config: &str, name: &str, log: &std::path::Path) -> (bool, String) {
+        let config = Config::parse(&config.replace("LOG", &log.to_string_lossy())).unwrap().resolved().unwrap();
+        let entry = config.entries.iter().find(|e| e.game_name == name).unwrap();
+        let context = CommandContext::for_entry(entry, SystemTime::now());
+        let rolled_back = run_start_commands(entry, &context, CommandOptions::new(&config, Some(entry)));
+        (rolled_back, fs::read_to_string(log).unwrap_or_default())
+    }
+
+    #[cfg(unix)]
+    const ROLLBACK: &str = r#"
+version = 1
+
+[templates.perf]
+start_commands = ["echo governor performance >> LOG"]
+end_commands = ["echo governor powersave >> LOG"]
+
+[[entries]]
+game_name = "Share"
+executable = "share"
+extends = "perf"
+on_failure = "rollback"
+start_commands = ["echo mount-share >> LOG", "exit 1"]
+end_commands = ["echo umount-share >> LOG"]
+
+[[entries]]
+game_name = "Broken"
+executable = "broken"
+extends = "perf"
+on_failure = "rollback"
+start_commands = ["exit 1"]
+end_commands = ["echo umount-share >> LOG"]
+"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn rollback_undoes_template_commands_with_their_own_end_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+
+        // The entry's start failed before mounting, so only the template is undone
+        let (rolled_back, output) = start(ROLLBACK, "Broken", &log);
+        assert!(rolled_back);
+        assert_eq!(output, "governor performance\ngovernor powersave\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rollback_runs_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+
+        let (rolled_back, output) = start(ROLLBACK, "Share", &log);
+        assert!(rolled_back);
+        assert_eq!(output, "governor performance\nmount-share\numount-share\ngovernor powersave\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parallel_rollback_undoes_every_command_that_succeeded() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("log");
+        let config = r#"
+version = 1
+
+[[entries]]
+game_name = "Parallel"
+executable = "parallel"
+mode = "parallel"
+on_failure = "rollback"
+start_commands = ["echo a >> LOG", "exit 1", "echo c >> LOG"]
+end_commands = ["echo undo-a >> LOG", "echo undo-b >> LOG", "echo undo-c >> LOG"]
+"#;
+
+        let (rolled_back, output) = start(config, "Parallel", &log);
+        assert!(rolled_back);
+        let mut lines: Vec<&str> = output.lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["a", "c", "undo-a", "undo-c"]);
+    }
 }
@@ -0,0 +1,741 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::process::ProcessInfo;
+
+/// A running game: the process that first matched its entry plus every
+/// descendant and later match. It ends only once all of them are gone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub game_name: String,
+    pub root_pid: u32,
+    pub pids: BTreeSet<u32>,
+    /// How the game exited, set on `SessionEvent::Ended` if the backend could tell.
+    pub exit: Option<ProcessExit>,
+}
+
+/// Signals that mean the program crashed rather than being told to stop.
+#[cfg(unix)]
+const CRASH_SIGNALS: &[i32] = &[
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+    libc::SIGTRAP,
+    libc::SIGSYS,
+];
+#[cfg(not(unix))]
+const CRASH_SIGNALS: &[i32] = &[];
+
+/// How a process exited: with a code, or killed by a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessExit {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub core_dumped: bool,
+}
+
+impl ProcessExit {
+    /// Decodes a wait status as the kernel reports it: the signal in the
+    /// low seven bits, the core dump flag next to it, the code above.
+    pub fn from_wait_status(status: u32) -> Self {
+        let signal = (status & 0x7f) as i32;
+        if signal == 0 {
+            Self { code: Some(((status >> 8) & 0xff) as i32), signal: None, core_dumped: false }
+        } else {
+            Self { code: None, signal: Some(signal), core_dumped: status & 0x80 != 0 }
+        }
+    }
+
+    pub fn kind(&self) -> ExitKind {
+        match (self.code, self.signal) {
+            (Some(0), _) => ExitKind::Clean,
+            (Some(_), _) => ExitKind::Failed,
+            (None, Some(signal)) if self.core_dumped || CRASH_SIGNALS.contains(&signal) => ExitKind::Crashed,
+            _ => ExitKind::Killed,
+        }
+    }
+}
+
+/// Ordered from least to most serious.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExitKind {
+    Clean,
+    /// Stopped by a signal such as SIGTERM or SIGKILL.
+    Killed,
+    /// Exited with a non-zero code.
+    Failed,
+    /// Died from a signal such as SIGSEGV, or dumped core.
+    Crashed,
+}
+
+impl std::fmt::Display for ExitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ExitKind::Clean => "clean",
+            ExitKind::Killed => "killed",
+            ExitKind::Failed => "failed",
+            ExitKind::Crashed => "crashed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEvent {
+    Started(Session),
+    /// The game came back within its grace period; the session goes on.
+    Resumed(Session),
+    /// The last process of the session exited and the grace period ran out;
+    /// carries its final PID set.
+    Ended(Session),
+}
+
+struct TrackedSession {
+    session: Session,
+    /// When the process tree was first seen empty, while waiting out the grace period.
+    missing_since: Option<Instant>,
+    /// The PIDs in the session that matched the entry themselves, as opposed
+    /// to helpers and other descendants.
+    matched: BTreeSet<u32>,
+    /// The last exit of a `matched` PID, which is how the game itself exited.
+    exit: Option<ProcessExit>,
+}
+
+/// Turns successive process table snapshots into session start/end events.
+#[derive(Default)]
+pub struct SessionTracker {
+    sessions: HashMap<String, TrackedSession>,
+}
+
+impl SessionTracker {
+    /// True when no session is running.
+    pub fn is_idle(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// True while any session is gone but still inside its grace period.
+    pub fn has_pending_end(&self) -> bool {
+        self.sessions.values().any(|tracked| tracked.missing_since.is_some())
+    }
+
+    /// Every PID belonging to a running session. Sessions inside their grace
+    /// period are left out: their PIDs are only kept for the end commands and
+    /// are long gone, so a wait on them would return at once.
+    pub fn pids(&self) -> Vec<u32> {
+        self.sessions
+            .values()
+            .filter(|tracked| tracked.missing_since.is_none())
+            .flat_map(|tracked| tracked.session.pids.iter().copied())
+            .collect()
+    }
+
+    /// Files exit statuses reported by the wait backend under the sessions
+    /// the PIDs belong to. Only processes that matched the entry count, so a
+    /// helper exiting with an error doesn't make a clean quit a crash. Of
+    /// several reported together, the most serious one is kept.
+    pub fn record_exits(&mut self, exits: &HashMap<u32, ProcessExit>) {
+        for tracked in self.sessions.values_mut() {
+            let exit = tracked
+                .matched
+                .iter()
+                .filter_map(|pid| exits.get(pid))
+                .max_by_key(|exit| exit.kind());
+            if let Some(exit) = exit {
+                tracked.exit = Some(*exit);
+            }
+        }
+    }
+
+    /// Updates every session from a new snapshot taken at `now`.
+    ///
+    /// `matched` maps game names to the PIDs matching their entries in
+    /// `processes`. A known session keeps whatever of its tree is still alive
+    /// plus new matches, so a launcher that restarts the game keeps the
+    /// session going. Once nothing is left the session ends after the game's
+    /// entry in `grace` (zero if absent), unless the game reappears first.
+    pub fn update(
+        &mut self,
+        matched: &HashMap<String, Vec<u32>>,
+        processes: &[ProcessInfo],
+        grace: &HashMap<String, Duration>,
+        now: Instant,
+    ) -> Vec<SessionEvent> {
+        let tree = ProcessTree::new(processes);
+        let mut events = Vec::new();
+
+        let mut ended = Vec::new();
+        for (game_name, tracked) in self.sessions.iter_mut() {
+            let mut seeds: Vec<u32> = tracked.session.pids.iter().copied().filter(|pid| tree.contains(*pid)).collect();
+            if let Some(pids) = matched.get(game_name) {
+                seeds.extend(pids);
+                tracked.matched.extend(pids);
+            }
+
+            let pids = tree.with_descendants(&seeds);
+            if !pids.is_empty() {
+                tracked.matched.retain(|pid| pids.contains(pid));
+                tracked.session.pids = pids;
+                if tracked.missing_since.take().is_some() {
+                    // The game is back, so how it exited before no longer matters
+                    tracked.exit = None;
+                    events.push(SessionEvent::Resumed(tracked.session.clone()));
+                }
+                continue;
+            }
+
+            // Keep the last known PIDs so end commands still see them
+            let missing_since = *tracked.missing_since.get_or_insert(now);
+            let grace = grace.get(game_name).copied().unwrap_or_default();
+            if now.saturating_duration_since(missing_since) >= grace {
+                ended.push(game_name.clone());
+            }
+        }
+
+        for game_name in ended {
+            if let Some(tracked) = self.sessions.remove(&game_name) {
+                events.push(SessionEvent::Ended(Session { exit: tracked.exit, ..tracked.session }));
+            }
+        }
+
+        for (game_name, pids) in matched {
+            if pids.is_empty() || self.sessions.contains_key(game_name) {
+                continue;
+            }
+
+            let session = Session {
+                game_name: game_name.clone(),
+                root_pid: tree.topmost(pids),
+                pids: tree.with_descendants(pids),
+                exit: None,
+            };
+            let tracked = TrackedSession {
+                session: session.clone(),
+                missing_since: None,
+                matched: pids.iter().copied().collect(),
+                exit: None,
+            };
+            self.sessions.insert(game_name.clone(), tracked);
+            events.push(SessionEvent::Started(session));
+        }
+
+        events
+    }
+}
+
+/// Counts holders of things shared by overlapping sessions, by name.
+/// The callbacks for the first acquire and the last release of a key run
+/// under that key's lock, so a release and a following acquire never
+/// interleave, while a slow callback holds up no other key.
+#[derive(Default)]
+pub struct RefCounter {
+    /// Keys are never removed, so every caller locks the same counter for a key.
+    counts: Mutex<HashMap<String, Arc<Mutex<usize>>>>,
+}
+
+impl RefCounter {
+    /// Adds a holder of `key`, calling `on_first` if there was none. Returns the new count.
+    pub fn acquire(&self, key: &str, on_first: impl FnOnce()) -> usize {
+        let counter = self.counter(key);
+        let mut count = counter.lock().unwrap_or_else(|e| e.into_inner());
+        if *count == 0 {
+            on_first();
+        }
+        *count += 1;
+        *count
+    }
+
+    /// Drops a holder of `key`, calling `on_last` if it was the last one. Returns the new count.
+    pub fn release(&self, key: &str, on_last: impl FnOnce()) -> usize {
+        let counter = self.counter(key);
+        let mut count = counter.lock().unwrap_or_else(|e| e.into_inner());
+        if *count == 0 {
+            return 0;
+        }
+
+        *count -= 1;
+        if *count == 0 {
+            on_last();
+        }
+        *count
+    }
+
+    /// Holders of `key`, once a first acquire or last release in progress has finished.
+    pub fn count(&self, key: &str) -> usize {
+        let counter = self.counter(key);
+        let count = *counter.lock().unwrap_or_else(|e| e.into_inner());
+        count
+    }
+
+    fn counter(&self, key: &str) -> Arc<Mutex<usize>> {
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.entry(key.to_string()).or_default().clone()
+    }
+}
+
+/// When each of a session's `while_running` commands is next due. Time is
+/// always passed in, like `SessionTracker::update`, rather than read here.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    /// Interval and next due time per command. Zero intervals are never due.
+    slots: Vec<(Duration, Option<Instant>)>,
+}
+
+impl Schedule {
+    /// Schedules each command one interval after `now`.
+    pub fn new(intervals: impl IntoIterator<Item = Duration>, now: Instant) -> Self {
+        let slots = intervals
+            .into_iter()
+            .map(|interval| (interval, (!interval.is_zero()).then(|| now + interval)))
+            .collect();
+        Self { slots }
+    }
+
+    /// How long until the next command is due, or `None` if none ever is.
+    pub fn wait_time(&self, now: Instant) -> Option<Duration> {
+        self.slots
+            .iter()
+            .filter_map(|(_, due)| *due)
+            .min()
+            .map(|due| due.saturating_duration_since(now))
+    }
+
+    /// Indices of the commands due at `now`, each rescheduled for its next
+    /// interval. Runs missed while the thread was busy are skipped rather
+    /// than made up in a burst.
+    pub fn take_due(&mut self, now: Instant) -> Vec<usize> {
+        let mut due_now = Vec::new();
+        for (i, (interval, due)) in self.slots.iter_mut().enumerate() {
+            let Some(at) = due else {
+                continue;
+            };
+            if *at > now {
+                continue;
+            }
+
+            due_now.push(i);
+            while *at <= now {
+                *at += *interval;
+            }
+        }
+        due_now
+    }
+}
+
+/// Parent/child index over one snapshot.
+struct ProcessTree {
+    parents: HashMap<u32, u32>,
+    children: HashMap<u32, Vec<u32>>,
+}
+
+impl ProcessTree {
+    fn new(processes: &[ProcessInfo]) -> Self {
+        let mut parents = HashMap::new();
+        let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+        for proc in processes {
+            parents.insert(proc.pid, proc.ppid);
+            children.entry(proc.ppid).or_default().push(proc.pid);
+        }
+        Self { parents, children }
+    }
+
+    fn contains(&self, pid: u32) -> bool {
+        self.parents.contains_key(&pid)
+    }
+
+    /// Live PIDs among `seeds` together with all of their live descendants.
+    fn with_descendants(&self, seeds: &[u32]) -> BTreeSet<u32> {
+        let mut found = BTreeSet::new();
+        let mut stack: Vec<u32> = seeds.iter().copied().filter(|pid| self.contains(*pid)).collect();
+
+        while let Some(pid) = stack.pop() {
+            if found.insert(pid) {
+                if let Some(children) = self.children.get(&pid) {
+                    stack.extend(children);
+                }
+            }
+        }
+
+        found
+    }
+
+    /// The PID among `pids` whose parent is not itself one of `pids`,
+    /// preferring the lowest PID when several qualify.
+    fn topmost(&self, pids: &[u32]) -> u32 {
+        pids.iter()
+            .copied()
+            .filter(|pid| self.parents.get(pid).is_none_or(|ppid| !pids.contains(ppid)))
+            .min()
+            .or_else(|| pids.iter().copied().min())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::ProcessTable;
+
+    /// Stands in for `/proc`, one snapshot at a time.
+    struct FakeTable(Vec<ProcessInfo>);
+
+    impl ProcessTable for FakeTable {
+        fn snapshot(&self) -> Vec<ProcessInfo> {
+            self.0.clone()
+        }
+    }
+
+    /// One watchdog scan for a "Game" entry matching processes named `game`.
+    fn scan(tracker: &mut SessionTracker, table: &FakeTable, grace: &HashMap<String, Duration>, now: Instant) -> Vec<SessionEvent> {
+        let processes = table.snapshot();
+        let pids: Vec<u32> = processes.iter().filter(|p| p.matches_executable("game")).map(|p| p.pid).collect();
+        let matched = if pids.is_empty() { HashMap::new() } else { matched("Game", &pids) };
+        tracker.update(&matched, &processes, grace, now)
+    }
+
+    fn grace(secs: u64) -> HashMap<String, Duration> {
+        HashMap::from([("Game".to_string(), Duration::from_secs(secs))])
+    }
+
+    fn process(pid: u32, ppid: u32, name: &str) -> ProcessInfo {
+        ProcessInfo { pid, ppid, name: name.to_string(), ..Default::default() }
+    }
+
+    fn matched(game_name: &str, pids: &[u32]) -> HashMap<String, Vec<u32>> {
+        HashMap::from([(game_name.to_string(), pids.to_vec())])
+    }
+
+    fn ended(events: &[SessionEvent]) -> Option<&Session> {
+        events.iter().find_map(|event| match event {
+            SessionEvent::Ended(session) => Some(session),
+            _ => None,
+        })
+    }
+
+    #[cfg(unix)]
+    /// Starts a session for a game (100) that runs a helper (101), then
+    /// reports `exits` and ends the session.
+    fn exit_after(exits: &[(u32, u32)]) -> Option<ProcessExit> {
+        let mut tracker = SessionTracker::default();
+        let grace = HashMap::new();
+        let t0 = Instant::now();
+
+        let game = process(100, 1, "game");
+        let helper = process(101, 100, "sh");
+        tracker.update(&matched("Game", &[100]), &[game, helper], &grace, t0);
+
+        for &(pid, status) in exits {
+            tracker.record_exits(&HashMap::from([(pid, ProcessExit::from_wait_status(status))]));
+        }
+        let events = tracker.update(&HashMap::new(), &[], &grace, t0 + Duration::from_secs(1));
+        ended(&events).unwrap().exit
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exit_kinds_only_call_crash_signals_crashes() {
+        assert_eq!(ProcessExit::from_wait_status(0).kind(), ExitKind::Clean);
+        assert_eq!(ProcessExit::from_wait_status(1 << 8).kind(), ExitKind::Failed);
+        assert_eq!(ProcessExit::from_wait_status(libc::SIGTERM as u32).kind(), ExitKind::Killed);
+        assert_eq!(ProcessExit::from_wait_status(libc::SIGKILL as u32).kind(), ExitKind::Killed);
+        assert_eq!(ProcessExit::from_wait_status(libc::SIGSEGV as u32).kind(), ExitKind::Crashed);
+        // SIGQUIT isn't a crash signal, but a core dump is
+        assert_eq!(ProcessExit::from_wait_status(libc::SIGQUIT as u32 | 0x80).kind(), ExitKind::Crashed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn helper_exits_do_not_decide_how_the_game_exited() {
+        let exit = exit_after(&[(101, 1 << 8), (100, 0)]).unwrap();
+        assert_eq!(exit.kind(), ExitKind::Clean);
+
+        let exit = exit_after(&[(100, libc::SIGSEGV as u32), (101, 0)]).unwrap();
+        assert_eq!(exit.kind(), ExitKind::Crashed);
+        assert_eq!(exit.signal, Some(libc::SIGSEGV));
+
+        assert_eq!(exit_after(&[(101, libc::SIGSEGV as u32)]), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_last_exit_of_the_game_counts() {
+        let mut tracker = SessionTracker::default();
+        let grace = HashMap::new();
+        let t0 = Instant::now();
+
+        let launcher = process(100, 1, "launcher");
+        tracker.update(&matched("Game", &[101]), &[launcher.clone(), process(101, 100, "game")], &grace, t0);
+
+        // The game crashes and the launcher starts it again, which then quits cleanly
+        tracker.record_exits(&HashMap::from([(101, ProcessExit::from_wait_status(libc::SIGSEGV as u32))]));
+        tracker.update(&matched("Game", &[102]), &[launcher, process(102, 100, "game")], &grace, t0 + Duration::from_secs(1));
+        tracker.record_exits(&HashMap::from([(102, ProcessExit::from_wait_status(0))]));
+
+        let events = tracker.update(&HashMap::new(), &[], &grace, t0 + Duration::from_secs(2));
+        assert_eq!(ended(&events).and_then(|session| session.exit).map(|exit| exit.kind()), Some(ExitKind::Clean));
+    }
+
+    #[test]
+    fn resuming_forgets_the_earlier_exit() {
+        let mut tracker = SessionTracker::default();
+        let grace = grace(10);
+        let t0 = Instant::now();
+
+        tracker.update(&matched("Game", &[100]), &[process(100, 1, "game")], &grace, t0);
+        tracker.record_exits(&HashMap::from([(100, ProcessExit::from_wait_status(1 << 8))]));
+        tracker.update(&HashMap::new(), &[], &grace, t0 + Duration::from_secs(1));
+
+        // Back within the grace period, then gone for good without a status
+        let events = tracker.update(&matched("Game", &[200]), &[process(200, 1, "game")], &grace, t0 + Duration::from_secs(2));
+        assert!(matches!(events.as_slice(), [SessionEvent::Resumed(_)]));
+        tracker.update(&HashMap::new(), &[], &grace, t0 + Duration::from_secs(3));
+        let events = tracker.update(&HashMap::new(), &[], &grace, t0 + Duration::from_secs(20));
+        assert_eq!(ended(&events).unwrap().exit, None);
+    }
+
+    #[test]
+    fn pids_leave_out_sessions_in_their_grace_period() {
+        let mut tracker = SessionTracker::default();
+        let grace = HashMap::from([("Game".to_string(), Duration::from_secs(10))]);
+        let t0 = Instant::now();
+
+        tracker.update(&matched("Game", &[100]), &[process(100, 1, "game")], &grace, t0);
+        assert_eq!(tracker.pids(), vec![100]);
+
+        tracker.update(&HashMap::new(), &[], &grace, t0 + Duration::from_secs(1));
+        assert!(tracker.has_pending_end());
+        assert!(tracker.pids().is_empty());
+    }
+
+    #[test]
+    fn session_resumes_when_the_game_returns_inside_the_grace_period() {
+        let mut tracker = SessionTracker::default();
+        let grace = grace(10);
+        let t0 = Instant::now();
+
+        let events = scan(&mut tracker, &FakeTable(vec![process(100, 1, "game")]), &grace, t0);
+        assert!(matches!(&events[..], [SessionEvent::Started(s)] if s.root_pid == 100));
+
+        let gone = FakeTable(Vec::new());
+        assert!(scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(1)).is_empty());
+        assert!(scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(9)).is_empty());
+
+        // Relaunched under a new PID, e.g. after an update
+        let events = scan(&mut tracker, &FakeTable(vec![process(200, 1, "game")]), &grace, t0 + Duration::from_secs(10));
+        assert!(matches!(&events[..], [SessionEvent::Resumed(s)] if s.pids == BTreeSet::from([200])));
+        assert!(!tracker.has_pending_end());
+
+        // The grace period starts over from the next exit
+        assert!(scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(30)).is_empty());
+        let events = scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(40));
+        assert!(matches!(&events[..], [SessionEvent::Ended(_)]));
+    }
+
+    #[test]
+    fn session_ends_once_the_grace_period_runs_out() {
+        let mut tracker = SessionTracker::default();
+        let grace = grace(10);
+        let t0 = Instant::now();
+
+        scan(&mut tracker, &FakeTable(vec![process(100, 1, "game"), process(101, 100, "helper")]), &grace, t0);
+
+        let gone = FakeTable(Vec::new());
+        assert!(scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(5)).is_empty());
+        assert!(scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(14)).is_empty());
+
+        let events = scan(&mut tracker, &gone, &grace, t0 + Duration::from_secs(15));
+        let session = ended(&events).unwrap();
+        // The end commands still see the last PIDs the game had
+        assert_eq!(session.pids, BTreeSet::from([100, 101]));
+        assert!(tracker.is_idle());
+
+        // Coming back after that is a new session
+        let events = scan(&mut tracker, &FakeTable(vec![process(300, 1, "game")]), &grace, t0 + Duration::from_secs(16));
+        assert!(matches!(&events[..], [SessionEvent::Started(_)]));
+    }
+
+    #[test]
+    fn session_ends_on_the_first_missing_scan_without_a_grace_period() {
+        let mut tracker = SessionTracker::default();
+        let t0 = Instant::now();
+
+        scan(&mut tracker, &FakeTable(vec![process(100, 1, "game")]), &HashMap::new(), t0);
+        let events = scan(&mut tracker, &FakeTable(Vec::new()), &HashMap::new(), t0 + Duration::from_secs(1));
+        assert!(ended(&events).is_some());
+    }
+
+    #[test]
+    fn launcher_restarting_the_game_keeps_the_session() {
+        let mut tracker = SessionTracker::default();
+        let grace = HashMap::new();
+        let t0 = Instant::now();
+
+        let launcher = process(100, 1, "launcher");
+        scan(&mut tracker, &FakeTable(vec![launcher.clone(), process(101, 100, "game")]), &grace, t0);
+
+        // The launcher replaces the game within one scan
+        let table = FakeTable(vec![launcher.clone(), process(102, 100, "game")]);
+        assert!(scan(&mut tracker, &table, &grace, t0 + Duration::from_secs(1)).is_empty());
+        assert_eq!(tracker.pids(), vec![102]);
+
+        // A child that outlives the game, like a crash reporter, keeps it going too
+        let table = FakeTable(vec![launcher.clone(), process(102, 100, "game"), process(103, 102, "reporter")]);
+        assert!(scan(&mut tracker, &table, &grace, t0 + Duration::from_secs(2)).is_empty());
+        let table = FakeTable(vec![launcher, process(103, 1, "reporter")]);
+        assert!(scan(&mut tracker, &table, &grace, t0 + Duration::from_secs(3)).is_empty());
+        assert_eq!(tracker.pids(), vec![103]);
+
+        let events = scan(&mut tracker, &FakeTable(Vec::new()), &grace, t0 + Duration::from_secs(4));
+        assert_eq!(ended(&events).map(|s| s.root_pid), Some(101));
+    }
+
+    #[test]
+    fn overlapping_sessions_share_a_resource() {
+        let counter = RefCounter::default();
+        let acquired = std::cell::Cell::new(0);
+        let released = std::cell::Cell::new(0);
+        let acquire = || acquired.set(acquired.get() + 1);
+        let release = || released.set(released.get() + 1);
+
+        // Game A starts, then game B while A is still running
+        assert_eq!(counter.acquire("governor", acquire), 1);
+        assert_eq!(counter.acquire("governor", acquire), 2);
+        assert_eq!(acquired.get(), 1);
+
+        // A ends first: B still holds the governor
+        assert_eq!(counter.release("governor", release), 1);
+        assert_eq!(released.get(), 0);
+        assert_eq!(counter.count("governor"), 1);
+
+        assert_eq!(counter.release("governor", release), 0);
+        assert_eq!(released.get(), 1);
+
+        // A new session after both ended acquires again
+        assert_eq!(counter.acquire("governor", acquire), 1);
+        assert_eq!(acquired.get(), 2);
+    }
+
+    #[test]
+    fn releasing_an_unheld_key_does_nothing() {
+        let counter = RefCounter::default();
+        assert_eq!(counter.release("audio", || panic!("nothing was acquired")), 0);
+        assert_eq!(counter.count("audio"), 0);
+
+        counter.acquire("audio", || {});
+        counter.release("audio", || {});
+        assert_eq!(counter.release("audio", || panic!("already released")), 0);
+    }
+
+    #[test]
+    fn slow_callback_only_holds_up_its_own_key() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        let counter = Arc::new(RefCounter::default());
+        let (started_tx, started_rx) = mpsc::channel();
+        let (go_tx, go_rx) = mpsc::channel::<()>();
+
+        // A hung acquire command for one resource
+        let slow = {
+            let counter = counter.clone();
+            thread::spawn(move || {
+                counter.acquire("network-mount", || {
+                    started_tx.send(()).unwrap();
+                    go_rx.recv().unwrap();
+                })
+            })
+        };
+        started_rx.recv().unwrap();
+
+        let (done_tx, done_rx) = mpsc::channel();
+        {
+            let counter = counter.clone();
+            thread::spawn(move || {
+                let count = counter.acquire("governor", || {});
+                counter.release("governor", || {});
+                done_tx.send(count).unwrap();
+            });
+        }
+        assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+
+        // The same key waits instead of running its first acquire twice
+        let second = {
+            let counter = counter.clone();
+            thread::spawn(move || counter.acquire("network-mount", || panic!("acquired twice")))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!second.is_finished());
+
+        go_tx.send(()).unwrap();
+        assert_eq!(slow.join().unwrap(), 1);
+        assert_eq!(second.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn concurrent_sessions_never_overlap_first_and_last_callbacks() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+
+        let counter = Arc::new(RefCounter::default());
+        let held = Arc::new(AtomicUsize::new(0));
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let counter = counter.clone();
+                let held = held.clone();
+                thread::spawn(move || {
+                    for _ in 0..200 {
+                        counter.acquire("sessions", || assert_eq!(held.fetch_add(1, Ordering::SeqCst), 0));
+                        counter.release("sessions", || assert_eq!(held.fetch_sub(1, Ordering::SeqCst), 1));
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(held.load(Ordering::SeqCst), 0);
+        assert_eq!(counter.count("sessions"), 0);
+    }
+
+    #[test]
+    fn scheduled_commands_first_run_one_interval_in() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new([Duration::from_secs(60), Duration::from_secs(90)], start);
+
+        assert_eq!(schedule.wait_time(start), Some(Duration::from_secs(60)));
+        assert!(schedule.take_due(start).is_empty());
+        assert!(schedule.take_due(start + Duration::from_secs(59)).is_empty());
+        assert_eq!(schedule.take_due(start + Duration::from_secs(60)), vec![0]);
+        assert_eq!(schedule.wait_time(start + Duration::from_secs(60)), Some(Duration::from_secs(30)));
+        assert_eq!(schedule.take_due(start + Duration::from_secs(90)), vec![1]);
+        assert_eq!(schedule.take_due(start + Duration::from_secs(120)), vec![0]);
+    }
+
+    #[test]
+    fn zero_interval_never_runs() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new([Duration::ZERO], start);
+
+        assert_eq!(schedule.wait_time(start), None);
+        assert!(schedule.take_due(start).is_empty());
+        assert!(schedule.take_due(start + Duration::from_secs(3600)).is_empty());
+
+        let mut schedule = Schedule::new([Duration::ZERO, Duration::from_secs(10)], start);
+        assert_eq!(schedule.take_due(start + Duration::from_secs(10)), vec![1]);
+    }
+
+    #[test]
+    fn missed_runs_are_skipped() {
+        let start = Instant::now();
+        let mut schedule = Schedule::new([Duration::from_secs(10)], start);
+
+        // Busy for 55s: one run now, not five
+        let late = start + Duration::from_secs(55);
+        assert_eq!(schedule.take_due(late), vec![0]);
+        assert!(schedule.take_due(late).is_empty());
+        // Back on the original grid
+        assert_eq!(schedule.wait_time(late), Some(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,96 @@
+use crate::process::ProcessInfo;
+
+/// Environment variables Steam and Proton use to pass the AppID to a game,
+/// in order of preference.
+const APP_ID_ENV: &[&str] = &["SteamAppId", "SteamGameId", "STEAM_COMPAT_APP_ID"];
+
+/// Returns the Steam AppID a process was launched for.
+///
+/// Games inherit `SteamAppId`/`SteamGameId` from Steam, Proton adds
+/// `STEAM_COMPAT_APP_ID`, and Steam's `reaper` supervisor carries
+/// `AppId=NNN` on its command line. Non-Steam shortcuts get a 64-bit game
+/// ID that is not a valid AppID and is ignored.
+pub fn app_id(proc: &ProcessInfo) -> Option<u32> {
+    APP_ID_ENV
+        .iter()
+        .filter_map(|key| proc.environ.get(*key))
+        .find_map(|value| parse_app_id(value))
+        .or_else(|| reaper_app_id(proc))
+}
+
+/// Reads `AppId=NNN` from the arguments of Steam's `reaper` process.
+fn reaper_app_id(proc: &ProcessInfo) -> Option<u32> {
+    if proc.name != "reaper" {
+        return None;
+    }
+
+    proc.cmdline
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .find_map(|arg| arg.strip_prefix("AppId="))
+        .and_then(parse_app_id)
+}
+
+fn parse_app_id(value: &str) -> Option<u32> {
+    value.trim().parse().ok().filter(|id| *id != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Env<'a> = &'a [(&'a str, &'a str)];
+
+    fn process(name: &str, cmdline: &[&str], env: Env) -> ProcessInfo {
+        ProcessInfo {
+            name: name.to_string(),
+            cmdline: cmdline.iter().map(|arg| arg.to_string()).collect(),
+            environ: env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn app_id_from_the_environment() {
+        let cases: &[(Env, Option<u32>)] = &[
+            (&[("SteamAppId", "570")], Some(570)),
+            (&[("SteamGameId", "570")], Some(570)),
+            (&[("STEAM_COMPAT_APP_ID", " 570 ")], Some(570)),
+            // SteamAppId is preferred, but only if it is usable
+            (&[("SteamAppId", "570"), ("SteamGameId", "440")], Some(570)),
+            (&[("SteamAppId", "0"), ("SteamGameId", "440")], Some(440)),
+            (&[("SteamAppId", "dota"), ("SteamGameId", "440")], Some(440)),
+            (&[("SteamAppId", "0")], None),
+            (&[("SteamAppId", "")], None),
+            (&[("SteamAppId", "-570")], None),
+            // Non-Steam shortcuts get a 64-bit game ID
+            (&[("SteamGameId", "15013221582350729216")], None),
+        ];
+
+        for (env, expected) in cases {
+            assert_eq!(app_id(&process("game", &["game"], env)), *expected, "{:?}", env);
+        }
+    }
+
+    #[test]
+    fn app_id_from_the_reaper_arguments() {
+        let cases: &[(&str, &[&str], Option<u32>)] = &[
+            ("reaper", &["reaper", "SteamLaunch", "AppId=1245620", "--", "/games/game"], Some(1245620)),
+            ("reaper", &["reaper", "AppId=0", "--", "/games/game"], None),
+            ("reaper", &["reaper", "AppId=abc", "--", "/games/game"], None),
+            // Arguments after `--` belong to the game
+            ("reaper", &["reaper", "SteamLaunch", "--", "/games/game", "AppId=440"], None),
+            // Only the reaper is trusted to carry it
+            ("game", &["game", "AppId=440"], None),
+        ];
+
+        for (name, cmdline, expected) in cases {
+            assert_eq!(app_id(&process(name, cmdline, &[])), *expected, "{:?}", cmdline);
+        }
+    }
+
+    #[test]
+    fn neither_environment_nor_reaper_means_no_app_id() {
+        assert_eq!(app_id(&process("game", &["/games/game", "-novid"], &[("HOME", "/home/me")])), None);
+    }
+}
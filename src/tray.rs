@@ -5,7 +5,8 @@ use gtk::prelude::{ApplicationExt, ApplicationExtManual, *};
 use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
-use crate::config::{Config, GAMEMON_CONFIG_FILE};
+use crate::companion;
+use crate::config::{profiles_dir_for, Config, GAMEMON_CONFIG_FILE};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher, EventKind};
 use glib::ControlFlow;
 use std::rc::Rc;
@@ -16,6 +17,10 @@ use tray_item::TrayItem;
 
 use crate::config::CURRENT_VERSION;
 
+/// How often the tray checks whether companion statuses changed.
+#[cfg(unix)]
+const COMPANION_REFRESH_SECS: u32 = 2;
+
 #[cfg(unix)]
 pub fn spawn_tray(
     sender: Sender<String>,
@@ -60,14 +65,30 @@ pub fn spawn_tray(
         let mut watcher = RecommendedWatcher::new(tx, notify::Config::default())
             .expect("Failed to create watcher");
         
-        // Start watching the specified configuration file
+        // Watch the config directory rather than the file itself: saves
+        // replace the file by renaming a temp file over it, which would
+        // leave a watch on the old file behind.
+        let config_dir = config_path.parent().map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."));
         watcher
-            .watch(config_path.as_path(), RecursiveMode::NonRecursive)
-            .expect("Failed to watch config file");
+            .watch(config_dir.as_path(), RecursiveMode::NonRecursive)
+            .expect("Failed to watch config directory");
+
+        // Drop-in profiles live in their own directory
+        let profiles_dir = profiles_dir_for(&config_path);
+        let _ = std::fs::create_dir_all(&profiles_dir);
+        if let Err(e) = watcher.watch(profiles_dir.as_path(), RecursiveMode::NonRecursive) {
+            log::warn!("Tray: failed to watch {}: {}", profiles_dir.display(), e);
+        }
 
         while let Ok(event) = rx.recv() {
             if let Ok(ev) = event {
-                if matches!(ev.kind, EventKind::Modify(_)) {
+                let is_config = ev.paths.iter().any(|p| {
+                    p == &config_path
+                        || (p.parent() == Some(profiles_dir.as_path())
+                            && p.extension().is_some_and(|ext| ext == "toml"))
+                });
+                let changed = matches!(ev.kind, EventKind::Modify(_) | EventKind::Create(_) | EventKind::Remove(_));
+                if is_config && changed {
                     // Send a message to the main context to trigger menu rebuild
                     glib_tx.send(()).unwrap();
                 }
@@ -88,11 +109,25 @@ pub fn spawn_tray(
         let sender_for_build = sender.clone();
         let menu_var = menu_var.clone();
 
+        // Last config that loaded, used while the file on disk is unreadable
+        let last_good: Rc<RefCell<Option<Config>>> = Rc::new(RefCell::new(None));
+
         // Function to build or rebuild the menu based on changes
         let build_menu = move || {
             let menu = menu_for_build.clone();
             let sender = sender_for_build.clone();
             let menu_ref = menu.borrow_mut();
+
+            let config = match Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
+                Ok(config) => {
+                    last_good.replace(Some(config.clone()));
+                    Some(config)
+                }
+                Err(e) => {
+                    log::warn!("Tray: could not load config, keeping the previous menu entries: {}", e);
+                    last_good.borrow().clone()
+                }
+            };
             
             // Clear existing children from the menu
             let children: Vec<_> = menu_ref.children().iter().cloned().collect();
@@ -119,13 +154,29 @@ pub fn spawn_tray(
                 menu_ref.append(&mi);
             }
 
+            // Toggle desktop notifications, persisted in the [settings] table
+            let notifications_item = gtk::CheckMenuItem::with_label("Notifications");
+            if let Some(config) = &config {
+                notifications_item.set_active(config.settings.notifications.enabled);
+            }
+            notifications_item.connect_toggled(|item| {
+                let config_path = GAMEMON_CONFIG_FILE.to_string_lossy();
+                if let Ok(mut config) = Config::load_from_file(&config_path) {
+                    config.settings.notifications.enabled = item.is_active();
+                    if let Err(e) = config.save_to_file(&config_path) {
+                        log::error!("Tray: failed to save notification setting: {}", e);
+                    }
+                }
+            });
+            menu_ref.append(&notifications_item);
+
             menu_ref.append(&gtk::SeparatorMenuItem::new());
 
             // Add a special "BOLOs" (Be On the Look Out for) section
             let bolos_item = gtk::MenuItem::with_label("BOLOs");
             let bolos_menu = gtk::Menu::new();
 
-            if let Ok(config) = Config::load_from_file(&GAMEMON_CONFIG_FILE.to_string_lossy()) {
+            if let Some(config) = config {
                 for entry in config.entries {
                     let game_name = entry.game_name.clone();
                     let game_name_start = game_name.clone();
@@ -155,6 +206,17 @@ pub fn spawn_tray(
 
                     sub.append(&start);
                     sub.append(&end);
+
+                    // Companions of a running session, as reported by the service
+                    let companions = companion::statuses(&game_name);
+                    if !companions.is_empty() {
+                        sub.append(&gtk::SeparatorMenuItem::new());
+                        for status in companions {
+                            let status_item = gtk::MenuItem::with_label(&status.to_string());
+                            status_item.set_sensitive(false);
+                            sub.append(&status_item);
+                        }
+                    }
                     sub.show_all();
 
                     item.set_submenu(Some(&sub));
@@ -187,6 +249,18 @@ pub fn spawn_tray(
             });
         }
 
+        // Rebuild the menu when a companion starts, crashes or stops
+        let build_menu_clone = build_menu.clone();
+        let mut companions_seen = companion::generation();
+        glib::timeout_add_seconds_local(COMPANION_REFRESH_SECS, move || {
+            let generation = companion::generation();
+            if generation != companions_seen {
+                companions_seen = generation;
+                build_menu_clone();
+            }
+            ControlFlow::Continue
+        });
+
         // Add a window to the application (this is likely unnecessary and can be removed)
         app.add_window(&gtk::Window::new(gtk::WindowType::Toplevel));
     });
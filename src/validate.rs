@@ -0,0 +1,580 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use glob::Pattern;
+use regex::Regex;
+use serde::Deserialize;
+use toml::{Spanned, Value};
+use crate::config::{line_col, profile_files, profiles_dir_for, CommandSpec, Config, ConfigError, Entry, FailurePolicy, RunMode, WatchBackend};
+use crate::migration::GUI_PLACEHOLDERS;
+use crate::service::BUILTIN_VARS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// One problem found in a config, pointing at the entry and field it is
+/// about and, when checked from the file's text, where that field is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The `profiles.d` file the problem is in, or `None` for the config file itself.
+    pub file: Option<PathBuf>,
+    /// Index into the config file's `entries`, for problems with a single entry there.
+    pub entry: Option<usize>,
+    pub game_name: Option<String>,
+    /// Field path within the entry or file, e.g. `start_commands[2]` or `match.cmdline`.
+    pub field: String,
+    pub message: String,
+    /// 1-based line and column.
+    pub location: Option<(usize, usize)>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: ")?,
+            Severity::Warning => write!(f, "warning: ")?,
+        }
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        if let Some((line, column)) = self.location {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        if let Some(index) = self.entry {
+            write!(f, "entries[{}] ", index)?;
+        }
+        match self.game_name.as_deref() {
+            Some(name) if !name.is_empty() => write!(f, "({}) ", name)?,
+            _ => {}
+        }
+        if !self.field.is_empty() {
+            write!(f, "{}: ", self.field)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+/// Checks an already loaded config. Diagnostics carry no location.
+pub fn validate(config: &Config) -> Vec<Diagnostic> {
+    let mut checker = Checker::new(None, None);
+    checker.check_config(config, None);
+    checker.diagnostics
+}
+
+/// Parses and checks the text of a config file, without its `profiles.d`.
+/// A file that doesn't parse yields a single error at the position TOML reports.
+pub fn validate_str(text: &str) -> Vec<Diagnostic> {
+    let config = match Config::parse(text) {
+        Ok(config) => config,
+        Err(e) => return vec![parse_error(None, e.as_ref())],
+    };
+
+    // Spans come from a loose second pass over the same text, which can't
+    // fail where the real parse succeeded
+    let spans = toml::from_str::<SpannedConfig>(text).ok();
+    let mut checker = Checker::new(Some(text), None);
+    checker.check_config(&config, spans.as_ref());
+    checker.diagnostics
+}
+
+/// Checks a config file and every profile in the `profiles.d` next to it.
+pub fn validate_file(path: &Path) -> io::Result<Vec<Diagnostic>> {
+    let text = fs::read_to_string(path)?;
+    let mut diagnostics = validate_str(&text);
+    let main = Config::parse(&text).ok();
+    let main_names: Vec<String> = main
+        .iter()
+        .flat_map(|config| config.entries.iter().map(|e| e.game_name.clone()))
+        .collect();
+
+    let mut profiles = Vec::new();
+    for file in profile_files(&profiles_dir_for(path))? {
+        let text = fs::read_to_string(&file)?;
+        match toml::from_str::<Entry>(&text) {
+            Ok(mut entry) => {
+                if entry.game_name.trim().is_empty() {
+                    entry.game_name = file.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+                }
+                profiles.push((file, text, entry));
+            }
+            Err(e) => diagnostics.push(parse_error(Some(file), &ConfigError::new(&text, &e))),
+        }
+    }
+
+    for (i, (file, text, entry)) in profiles.iter().enumerate() {
+        let spans = toml::from_str::<SpannedEntry>(text).ok();
+        let mut checker = Checker::new(Some(text), Some(file.clone()));
+        checker.check_entry(None, entry, spans.as_ref());
+        if let Some(main) = &main {
+            checker.check_extends(None, entry, main, spans.as_ref());
+        }
+
+        let overridden_by = if main_names.contains(&entry.game_name) {
+            Some("the entry of the same name in the config file".to_string())
+        } else {
+            profiles[i + 1..]
+                .iter()
+                .find(|(_, _, later)| later.game_name == entry.game_name)
+                .map(|(later, _, _)| later.display().to_string())
+        };
+        if let Some(by) = overridden_by {
+            let span = spans.as_ref().and_then(|e| e.game_name.as_ref()).map(Spanned::span);
+            let message = format!("not used, overridden by {}", by);
+            checker.push(Severity::Warning, None, Some(entry), "game_name", span, message);
+        }
+
+        diagnostics.extend(checker.diagnostics);
+    }
+
+    Ok(diagnostics)
+}
+
+fn parse_error(file: Option<PathBuf>, e: &(dyn std::error::Error + 'static)) -> Diagnostic {
+    let (message, location) = match e.downcast_ref::<ConfigError>() {
+        Some(e) => (e.message.clone(), e.location),
+        None => (e.to_string(), None),
+    };
+    Diagnostic {
+        severity: Severity::Error,
+        file,
+        entry: None,
+        game_name: None,
+        field: String::new(),
+        message,
+        location,
+    }
+}
+
+/// Mirror of the file layout that only records where fields are.
+#[derive(Deserialize, Default)]
+struct SpannedConfig {
+    #[serde(default)]
+    service: BTreeMap<String, Spanned<Value>>,
+    #[serde(default)]
+    templates: BTreeMap<String, SpannedTemplate>,
+    #[serde(default)]
+    entries: Vec<SpannedEntry>,
+}
+
+#[derive(Deserialize, Default)]
+struct SpannedTemplate {
+    extends: Option<Spanned<Value>>,
+}
+
+#[derive(Deserialize, Default)]
+struct SpannedEntry {
+    game_name: Option<Spanned<Value>>,
+    extends: Option<Spanned<Value>>,
+    #[serde(alias = "resource")]
+    resources: Option<Spanned<Value>>,
+    executable: Option<Spanned<Value>>,
+    start_commands: Option<Spanned<Vec<Spanned<Value>>>>,
+    end_commands: Option<Spanned<Vec<Spanned<Value>>>>,
+    on_crash: Option<Spanned<Vec<Spanned<Value>>>>,
+    companions: Option<Spanned<Vec<Spanned<Value>>>>,
+    while_running: Option<Spanned<Vec<Spanned<Value>>>>,
+    on_failure: Option<Spanned<Value>>,
+    vars: Option<BTreeMap<String, Spanned<Value>>>,
+    #[serde(rename = "match")]
+    match_rules: Option<BTreeMap<String, Spanned<Value>>>,
+}
+
+impl SpannedEntry {
+    fn commands(&self, field: &str) -> Option<&Spanned<Vec<Spanned<Value>>>> {
+        match field {
+            "start_commands" => self.start_commands.as_ref(),
+            "on_crash" => self.on_crash.as_ref(),
+            "companions" => self.companions.as_ref(),
+            "while_running" => self.while_running.as_ref(),
+            _ => self.end_commands.as_ref(),
+        }
+    }
+}
+
+/// Why `on_crash` commands do nothing with the default backend.
+const NO_EXIT_STATUS: &str = "never runs: exit statuses are only known with backend = \"events\"";
+
+struct Checker<'a> {
+    text: Option<&'a str>,
+    file: Option<PathBuf>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(text: Option<&'a str>, file: Option<PathBuf>) -> Self {
+        Self { text, file, diagnostics: Vec::new() }
+    }
+
+    fn check_config(&mut self, config: &Config, spans: Option<&SpannedConfig>) {
+        for key in ["poll_interval", "idle_poll_interval", "fast_poll_interval"] {
+            let value = match key {
+                "poll_interval" => config.service.poll_interval,
+                "idle_poll_interval" => config.service.idle_poll_interval,
+                _ => config.service.fast_poll_interval,
+            };
+            if value == 0 {
+                let span = spans.and_then(|s| s.service.get(key)).map(Spanned::span);
+                self.push(Severity::Warning, None, None, &format!("service.{}", key), span, "0 is treated as 1 second");
+            }
+        }
+
+        // Loops and missing parents among templates, whether used or not
+        for (name, template) in &config.templates {
+            if template.extends.is_none() {
+                continue;
+            }
+            if let Err(e) = config.resolve_template(name, name, &mut Vec::new()) {
+                let span = spans
+                    .and_then(|s| s.templates.get(name))
+                    .and_then(|t| t.extends.as_ref())
+                    .map(Spanned::span);
+                self.push(Severity::Error, None, None, &format!("templates.{}.extends", name), span, e.to_string());
+            }
+        }
+
+        let mut first_index: HashMap<&str, usize> = HashMap::new();
+        for (index, entry) in config.entries.iter().enumerate() {
+            let entry_spans = spans.and_then(|s| s.entries.get(index));
+            if let Some(first) = first_index.get(entry.game_name.as_str()) {
+                let span = entry_spans.and_then(|e| e.game_name.as_ref()).map(Spanned::span);
+                let message = format!("duplicate game name, already used by entries[{}]", first);
+                self.push(Severity::Error, Some(index), Some(entry), "game_name", span, message);
+            } else if !entry.game_name.trim().is_empty() {
+                first_index.insert(&entry.game_name, index);
+            }
+
+            self.check_entry(Some(index), entry, entry_spans);
+            self.check_extends(Some(index), entry, config, entry_spans);
+
+            if config.service.backend != WatchBackend::Events && !entry.on_crash.is_empty() {
+                let span = entry_spans.and_then(|e| e.on_crash.as_ref()).map(Spanned::span);
+                self.push(Severity::Warning, Some(index), Some(entry), "on_crash", span, NO_EXIT_STATUS);
+            }
+        }
+
+        if config.service.backend != WatchBackend::Events && !config.hooks.on_crash.is_empty() {
+            self.push(Severity::Warning, None, None, "hooks.on_crash", None, NO_EXIT_STATUS);
+        }
+    }
+
+    /// Checks what the entry refers to elsewhere in `config`: its template and resources.
+    fn check_extends(&mut self, index: Option<usize>, entry: &Entry, config: &Config, spans: Option<&SpannedEntry>) {
+        let resolved = match config.resolve_entry(entry) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let span = spans.and_then(|e| e.extends.as_ref()).map(Spanned::span);
+                self.push(Severity::Error, index, Some(entry), "extends", span, e.to_string());
+                return;
+            }
+        };
+
+        for name in resolved.resources.iter().filter(|name| !config.resources.contains_key(*name)) {
+            let span = spans.and_then(|e| e.resources.as_ref()).map(Spanned::span);
+            let message = format!("unknown resource '{}'", name);
+            self.push(Severity::Error, index, Some(entry), "resources", span, message);
+        }
+    }
+
+    fn check_entry(&mut self, index: Option<usize>, entry: &Entry, spans: Option<&SpannedEntry>) {
+        if entry.game_name.trim().is_empty() {
+            let span = spans.and_then(|e| e.game_name.as_ref()).map(Spanned::span);
+            self.push(Severity::Error, index, Some(entry), "game_name", span, "game name is empty");
+        }
+
+        self.check_matching(index, entry, spans);
+
+        if entry.mode == RunMode::Parallel && entry.on_failure == FailurePolicy::Abort {
+            let span = spans.and_then(|e| e.on_failure.as_ref()).map(Spanned::span);
+            let message = "parallel start commands have all started by the time one fails, so there is nothing to abort";
+            self.push(Severity::Warning, index, Some(entry), "on_failure", span, message);
+        }
+
+        for field in ["start_commands", "end_commands", "on_crash"] {
+            let commands = match field {
+                "start_commands" => &entry.start_commands,
+                "on_crash" => &entry.on_crash,
+                _ => &entry.end_commands,
+            };
+            for (i, command) in commands.iter().enumerate() {
+                let span = spans
+                    .and_then(|e| e.commands(field))
+                    .and_then(|c| c.get_ref().get(i))
+                    .map(Spanned::span);
+                let field = format!("{}[{}]", field, i);
+                match command {
+                    CommandSpec::Shell(cmd) if cmd.trim().is_empty() => {
+                        self.push(Severity::Warning, index, Some(entry), &field, span, "empty command line");
+                    }
+                    CommandSpec::Shell(cmd) if GUI_PLACEHOLDERS.contains(&cmd.as_str()) => {
+                        self.push(Severity::Error, index, Some(entry), &field, span, "GUI placeholder text saved as a command");
+                    }
+                    CommandSpec::Program(cmd) if cmd.program.trim().is_empty() => {
+                        self.push(Severity::Error, index, Some(entry), &field, span, "program is empty");
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        for (i, companion) in entry.companions.iter().enumerate() {
+            if companion.command.is_blank() {
+                let span = spans
+                    .and_then(|e| e.commands("companions"))
+                    .and_then(|c| c.get_ref().get(i))
+                    .map(Spanned::span);
+                let field = format!("companions[{}]", i);
+                self.push(Severity::Error, index, Some(entry), &field, span, "companion has no command");
+            }
+        }
+
+        for (i, periodic) in entry.while_running.iter().enumerate() {
+            let span = spans
+                .and_then(|e| e.commands("while_running"))
+                .and_then(|c| c.get_ref().get(i))
+                .map(Spanned::span);
+            let field = format!("while_running[{}]", i);
+            if periodic.command.is_blank() {
+                self.push(Severity::Warning, index, Some(entry), &field, span.clone(), "empty command line");
+            }
+            if periodic.interval == 0 {
+                self.push(Severity::Warning, index, Some(entry), &field, span, "interval is 0, so the command never runs");
+            }
+        }
+
+        // Names differing only in case export the same environment variable
+        let mut exported: HashMap<String, &str> = HashMap::new();
+        for name in entry.vars.keys() {
+            let span = spans.and_then(|e| e.vars.as_ref()?.get(name)).map(Spanned::span);
+            let field = format!("vars.{}", name);
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                let message = "variable names may only use letters, digits and underscores";
+                self.push(Severity::Error, index, Some(entry), &field, span, message);
+            } else if let Some(other) = exported.insert(name.to_uppercase(), name) {
+                let message = format!("exported as GAMEMON_VAR_{} like vars.{}", name.to_uppercase(), other);
+                self.push(Severity::Error, index, Some(entry), &field, span, message);
+            } else if BUILTIN_VARS.contains(&name.as_str()) {
+                let message = format!("hidden by the built-in {{{}}} placeholder", name);
+                self.push(Severity::Warning, index, Some(entry), &field, span, message);
+            }
+        }
+    }
+
+    /// An entry needs something to match on, and patterns that compile.
+    fn check_matching(&mut self, index: Option<usize>, entry: &Entry, spans: Option<&SpannedEntry>) {
+        let executable_span = spans.and_then(|e| e.executable.as_ref()).map(Spanned::span);
+        let match_span = |key: &str| spans.and_then(|e| e.match_rules.as_ref()?.get(key)).map(Spanned::span);
+        let rules = entry.match_rules.clone().unwrap_or_default();
+
+        if GUI_PLACEHOLDERS.contains(&entry.executable.as_str()) {
+            let message = "GUI placeholder text saved as the executable";
+            self.push(Severity::Error, index, Some(entry), "executable", executable_span, message);
+        } else if rules.is_empty() && entry.executable.trim().is_empty() {
+            let message = "no executable or match rules, this entry never matches";
+            self.push(Severity::Error, index, Some(entry), "executable", executable_span, message);
+        } else if !rules.is_empty() && !entry.executable.is_empty() {
+            let message = "ignored because match rules are set";
+            self.push(Severity::Warning, index, Some(entry), "executable", executable_span, message);
+        }
+
+        if let Some(path) = &rules.path {
+            if let Err(e) = Pattern::new(path) {
+                let message = format!("invalid glob: {}", e);
+                self.push(Severity::Error, index, Some(entry), "match.path", match_span("path"), message);
+            }
+        }
+
+        let regexes = rules.cmdline.iter().map(|re| ("cmdline", re)).chain(rules.exclude.iter().map(|re| ("exclude", re)));
+        for (key, re) in regexes {
+            if let Err(e) = Regex::new(re) {
+                let message = format!("invalid regex: {}", e.to_string().lines().last().unwrap_or_default().trim());
+                self.push(Severity::Error, index, Some(entry), &format!("match.{}", key), match_span(key), message);
+            }
+        }
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        index: Option<usize>,
+        entry: Option<&Entry>,
+        field: &str,
+        span: Option<Range<usize>>,
+        message: impl Into<String>,
+    ) {
+        let location = match (self.text, span) {
+            (Some(text), Some(span)) => Some(line_col(text, span.start)),
+            _ => None,
+        };
+        self.diagnostics.push(Diagnostic {
+            severity,
+            file: self.file.clone(),
+            entry: index,
+            game_name: entry.map(|e| e.game_name.clone()),
+            field: field.to_string(),
+            message: message.into(),
+            location,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn var_names_that_export_the_same_variable_are_rejected() {
+        let diagnostics = validate_str(
+            r#"
+version = 1
+
+[[entries]]
+game_name = "Game"
+executable = "game"
+vars = { fan = "full", FAN = "quiet", fan_speed = "80" }
+"#,
+        );
+
+        let errors: Vec<_> = diagnostics.iter().filter(|d| d.severity == Severity::Error).collect();
+        assert_eq!(errors.len(), 1, "{:?}", diagnostics);
+        assert_eq!(errors[0].field, "vars.fan");
+        assert_eq!(errors[0].message, "exported as GAMEMON_VAR_FAN like vars.FAN");
+        assert!(errors[0].location.is_some());
+    }
+
+    #[test]
+    fn abort_has_nothing_to_stop_in_parallel() {
+        let diagnostics = validate_str(
+            r#"
+version = 1
+
+[[entries]]
+game_name = "Game"
+executable = "game"
+mode = "parallel"
+on_failure = "abort"
+"#,
+        );
+
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!((diagnostics[0].severity, diagnostics[0].field.as_str()), (Severity::Warning, "on_failure"));
+        assert_eq!(diagnostics[0].location, Some((8, 14)));
+    }
+
+    #[test]
+    fn on_crash_needs_the_events_backend() {
+        let text = r#"
+version = 1
+
+[hooks]
+on_crash = ["notify-send crashed"]
+
+[[entries]]
+game_name = "Game"
+executable = "game"
+on_crash = ["notify-send 'Game crashed'"]
+"#;
+
+        let fields: Vec<_> = validate_str(text).into_iter().map(|d| (d.severity, d.field, d.location)).collect();
+        assert_eq!(
+            fields,
+            [
+                (Severity::Warning, "on_crash".to_string(), Some((10, 12))),
+                (Severity::Warning, "hooks.on_crash".to_string(), None),
+            ]
+        );
+
+        let events = text.replace("[hooks]", "[service]\nbackend = \"events\"\n\n[hooks]");
+        assert!(validate_str(&events).is_empty());
+    }
+
+    type Location = Option<(usize, usize)>;
+
+    /// Severity, field and location of each diagnostic, in order.
+    fn summary(text: &str) -> Vec<(Severity, String, Location)> {
+        validate_str(text).into_iter().map(|d| (d.severity, d.field, d.location)).collect()
+    }
+
+    #[test]
+    fn duplicate_game_names_point_at_the_later_entry() {
+        let diagnostics = validate_str(
+            r#"
+version = 1
+
+[[entries]]
+game_name = "Game"
+executable = "game"
+
+[[entries]]
+game_name = "Game"
+executable = "game-beta"
+"#,
+        );
+
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].entry, Some(1));
+        assert_eq!(diagnostics[0].message, "duplicate game name, already used by entries[0]");
+        assert_eq!(diagnostics[0].location, Some((9, 13)));
+    }
+
+    #[test]
+    fn an_entry_needs_an_executable_or_match_rules() {
+        let text = r#"
+version = 1
+
+[[entries]]
+game_name = "Game"
+executable = ""
+"#;
+        assert_eq!(summary(text), [(Severity::Error, "executable".to_string(), Some((6, 14)))]);
+
+        let matched = format!("{}[entries.match]\nname = \"game\"\n", text);
+        assert_eq!(summary(&matched), []);
+    }
+
+    #[test]
+    fn unknown_templates_are_errors_at_extends() {
+        let diagnostics = validate_str(
+            r#"
+version = 1
+
+[templates.perf]
+start_commands = ["gamemoded -r"]
+
+[[entries]]
+game_name = "Game"
+executable = "game"
+extends = "perfomance"
+"#,
+        );
+
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!((diagnostics[0].severity, diagnostics[0].field.as_str()), (Severity::Error, "extends"));
+        assert!(diagnostics[0].message.contains("perfomance"), "{}", diagnostics[0].message);
+        assert_eq!(diagnostics[0].location, Some((10, 11)));
+    }
+
+    #[test]
+    fn locations_count_blank_lines_and_ignore_carriage_returns() {
+        let text = "version = 1\n\n\n[[entries]]\ngame_name = \"Game\"\n\nexecutable = \"game\"\nstart_commands = [\"\", \"true\"]\n";
+        let expected = [(Severity::Warning, "start_commands[0]".to_string(), Some((8, 19)))];
+        assert_eq!(summary(text), expected);
+        assert_eq!(summary(&text.replace('\n', "\r\n")), expected);
+
+        // Parse errors are placed the same way
+        let broken = "version = 1\n\n[[entries]]\ngame_name = \"Game\nexecutable = \"game\"\n";
+        let location = validate_str(broken)[0].location;
+        assert_eq!(location.map(|(line, _)| line), Some(4));
+        assert_eq!(validate_str(&broken.replace('\n', "\r\n"))[0].location, location);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::process::ProcessInfo;
+
+/// Images Wine and Proton start for their own plumbing. They carry `.exe`
+/// names but never identify the game.
+const WINE_SYSTEM_IMAGES: &[&str] = &[
+    "conhost.exe",
+    "explorer.exe",
+    "plugplay.exe",
+    "rpcss.exe",
+    "services.exe",
+    "start.exe",
+    "steam.exe",
+    "svchost.exe",
+    "tabtip.exe",
+    "winedbg.exe",
+    "winedevice.exe",
+    "wineboot.exe",
+];
+
+/// Returns true if `proc` looks like it runs under Wine or Steam Proton:
+/// a `wine*` loader, a process with Proton's `STEAM_COMPAT_*` environment
+/// (this covers pressure-vessel children), or a Windows-style `argv[0]`.
+pub fn is_wine_process(proc: &ProcessInfo) -> bool {
+    let loader = |name: &str| name.to_ascii_lowercase().starts_with("wine");
+
+    loader(&proc.name)
+        || proc
+            .exe
+            .as_deref()
+            .and_then(|exe| exe.file_name())
+            .and_then(|n| n.to_str())
+            .is_some_and(loader)
+        || proc.environ.contains_key("STEAM_COMPAT_DATA_PATH")
+        || proc.environ.contains_key("WINEPREFIX")
+        || proc.cmdline.first().is_some_and(|arg0| is_windows_path(arg0))
+}
+
+/// Arguments Wine's `start` and Proton take before the image they run.
+const LOADER_VERBS: &[&str] = &["start", "start.exe", "run", "waitforexitandrun", "runinprefix", "--"];
+
+/// Returns the full Windows path of the image a Wine process is running,
+/// e.g. `Z:\home\me\Games\Game\Game.exe`, as it appears in argv.
+///
+/// A running image shows up as argv[0]. Before that, it is the first
+/// argument after the `wine` or `proton` loader and the loader's own verbs
+/// and `/flags`; only if that isn't an `.exe` is any later `.exe` taken.
+/// Processes without either, like native tools that inherited `WINEPREFIX`,
+/// have no image.
+pub fn windows_image_path(proc: &ProcessInfo) -> Option<&str> {
+    if !is_wine_process(proc) {
+        return None;
+    }
+
+    let args = match proc.cmdline.first() {
+        Some(arg0) if is_windows_path(arg0) => &proc.cmdline[..],
+        _ => {
+            let loader = proc.cmdline.iter().rposition(|arg| is_loader(arg))?;
+            &proc.cmdline[loader + 1..]
+        }
+    };
+
+    let skipped = |arg: &&String| {
+        LOADER_VERBS.contains(&arg.to_ascii_lowercase().as_str()) || is_flag(arg) || is_system_image(arg)
+    };
+    args.iter()
+        .find(|arg| !skipped(arg))
+        .filter(|arg| is_image(arg))
+        .or_else(|| args.iter().find(|arg| is_image(arg)))
+        .map(String::as_str)
+}
+
+/// Returns the Windows image name of a Wine process, e.g. `Game.exe`.
+pub fn windows_image(proc: &ProcessInfo) -> Option<&str> {
+    windows_image_path(proc).map(windows_file_name)
+}
+
+/// An `.exe` that isn't one of Wine's own.
+fn is_image(arg: &str) -> bool {
+    windows_file_name(arg).to_ascii_lowercase().ends_with(".exe") && !is_system_image(arg)
+}
+
+fn is_system_image(arg: &str) -> bool {
+    WINE_SYSTEM_IMAGES.contains(&windows_file_name(arg).to_ascii_lowercase().as_str())
+}
+
+/// `wine`, `wine64`, a preloader or Proton's `proton` script, by any path.
+fn is_loader(arg: &str) -> bool {
+    let name = windows_file_name(arg).to_ascii_lowercase();
+    (name.starts_with("wine") && !name.ends_with(".exe")) || name == "proton"
+}
+
+/// Options such as `/unix` or `/desktop=Game,1920x1080`, as opposed to Unix paths.
+fn is_flag(arg: &str) -> bool {
+    arg.strip_prefix('/').is_some_and(|rest| !rest.is_empty() && !rest.contains('/'))
+}
+
+/// Last component of a path using either Windows or Unix separators.
+fn windows_file_name(path: &str) -> &str {
+    path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+/// True for drive-letter paths such as `Z:\...` or `C:/...`.
+fn is_windows_path(arg: &str) -> bool {
+    let bytes = arg.as_bytes();
+    bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' && matches!(bytes[2], b'\\' | b'/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn process(name: &str, cmdline: &[&str], env: &[(&str, &str)]) -> ProcessInfo {
+        ProcessInfo {
+            name: name.to_string(),
+            cmdline: cmdline.iter().map(|arg| arg.to_string()).collect(),
+            environ: env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<_, _>>(),
+            ..Default::default()
+        }
+    }
+
+    const PREFIX: &[(&str, &str)] = &[("WINEPREFIX", "/home/me/.wine")];
+
+    #[test]
+    fn plain_wine_runs_the_first_argument() {
+        let proc = process("wine", &["wine", "game.exe", "-windowed"], &[]);
+        assert_eq!(windows_image(&proc), Some("game.exe"));
+
+        let proc = process("game.exe", &["C:\\Games\\Game\\game.exe", "-windowed"], PREFIX);
+        assert_eq!(windows_image_path(&proc), Some("C:\\Games\\Game\\game.exe"));
+    }
+
+    #[test]
+    fn proton_waitforexitandrun_chain() {
+        let proc = process(
+            "python3",
+            &[
+                "/steam/ubuntu12_32/reaper",
+                "SteamLaunch",
+                "AppId=1245620",
+                "--",
+                "/steam/SteamLinuxRuntime_sniper/_v2-entry-point",
+                "--verb=waitforexitandrun",
+                "--",
+                "/steam/common/Proton 9.0/proton",
+                "waitforexitandrun",
+                "/games/ELDEN RING/Game/start_protected_game.exe",
+            ],
+            &[("STEAM_COMPAT_DATA_PATH", "/steam/compatdata/1245620")],
+        );
+        assert_eq!(windows_image(&proc), Some("start_protected_game.exe"));
+
+        // Proton's own steam.exe stub in front of the game
+        let proc = process(
+            "steam.exe",
+            &["c:\\windows\\system32\\steam.exe", "Z:\\games\\ELDEN RING\\Game\\eldenring.exe"],
+            &[("STEAM_COMPAT_DATA_PATH", "/steam/compatdata/1245620")],
+        );
+        assert_eq!(windows_image(&proc), Some("eldenring.exe"));
+    }
+
+    #[test]
+    fn native_processes_with_a_wine_environment_have_no_image() {
+        let proc = process("python3", &["/usr/bin/python3", "patcher.py", "--target", "game.exe"], PREFIX);
+        assert!(is_wine_process(&proc));
+        assert_eq!(windows_image(&proc), None);
+    }
+
+    #[test]
+    fn exe_arguments_are_not_the_image() {
+        let proc = process("wine", &["wine", "start", "/unix", "/games/launcher.exe", "game.exe"], PREFIX);
+        assert_eq!(windows_image(&proc), Some("launcher.exe"));
+
+        let proc = process("Launcher.exe", &["Z:\\games\\Launcher.exe", "--run", "Z:\\games\\Game.exe"], PREFIX);
+        assert_eq!(windows_image(&proc), Some("Launcher.exe"));
+
+        let proc = process("wine", &["wine", "explorer", "/desktop=Game,1920x1080", "game.exe"], PREFIX);
+        assert_eq!(windows_image(&proc), Some("game.exe"));
+    }
+
+    #[test]
+    fn a_non_exe_after_the_loader_falls_back_to_a_later_exe() {
+        let proc = process("wine", &["wine", "cmd", "/c", "Z:\\games\\Game.exe"], PREFIX);
+        assert_eq!(windows_image(&proc), Some("Game.exe"));
+    }
+}